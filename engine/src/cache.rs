@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io;
+use std::iter;
+use std::time::UNIX_EPOCH;
+
+use implicit_clone::sync::IString;
+use itertools::Itertools;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::front_matter::find_front_matter;
+use crate::sync::path::IPath;
+use crate::sync::path::ToIPath;
+use crate::utils::iter_deep;
+use crate::utils::split_into_name_pre_ext;
+
+/// Hashes of everything an output file was generated from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inputs {
+    /// Page file, if the output is a page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<IString>,
+    /// Meta file and page files with front matter, if the output is a page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<IString>,
+    /// All templates, if the output is rendered with templates (which can include each other).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub templates: Option<IString>,
+    /// Languages, i18ns, tags, metas (including front matter), list of page files and output
+    /// options. Every output depends on these (e.g. pages link to other pages by their titles).
+    pub stores: IString,
+    /// Version of the engine that generated the output.
+    pub engine: IString,
+}
+
+/// Inputs of every output file, by their paths relative to the output directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cache {
+    /// Output directory the outputs were generated into.
+    pub out_dir: String,
+    pub outputs: BTreeMap<String, Inputs>,
+}
+
+impl Cache {
+    /// Read the cache at `path` of builds into `out_dir`. Missing or invalid cache, or cache of
+    /// another output directory is treated as empty.
+    pub fn read(path: IPath, out_dir: IPath) -> Cache {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|source| toml::from_str::<Cache>(&source).ok())
+            .filter(|cache| cache.out_dir == *out_dir.to_string_lossy())
+            .unwrap_or_default()
+    }
+
+    pub fn write(&self, path: IPath) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(
+            path,
+            toml::to_string(self).expect("cache should be serializable"),
+        )
+    }
+}
+
+/// Outputs of a build, compared against the cache of the previous build.
+pub struct BuildCache {
+    /// Cache file, kept outside of the output directory so that it is not deployed along with it.
+    path: IPath,
+    out_dir: IPath,
+    old: Cache,
+    new: Cache,
+    /// All outputs of this build, including those that failed and are not cached.
+    produced: BTreeSet<String>,
+}
+
+impl BuildCache {
+    /// Start a build into `out_dir`, reusing the cache at `path` unless `force` is set.
+    pub fn new(path: IPath, out_dir: IPath, force: bool) -> BuildCache {
+        BuildCache {
+            old: match force {
+                true => Cache::default(),
+                false => Cache::read(path.clone(), out_dir.clone()),
+            },
+            new: Cache {
+                out_dir: out_dir.to_string_lossy().to_string(),
+                ..Cache::default()
+            },
+            produced: BTreeSet::new(),
+            path,
+            out_dir,
+        }
+    }
+
+    /// Path of an output relative to the output directory. Outputs outside of it (e.g. `robots.txt`
+    /// at the root of the host) are relative to its closest ancestor containing them.
+    fn key(&self, path: IPath) -> String {
+        for (up, dir) in self.out_dir.ancestors().enumerate() {
+            if let Ok(relative) = path.strip_prefix(dir.to_ipath()) {
+                let parents = iter::repeat_n(IString::from(".."), up);
+                return parents.chain(relative.into_iter_lossy()).join("/");
+            }
+        }
+        path.into_iter_lossy().join("/")
+    }
+
+    /// Check if the output at `path` still exists and was generated from the same `inputs`. Such
+    /// output does not have to be generated again.
+    pub fn is_up_to_date(&mut self, path: IPath, inputs: &Inputs) -> bool {
+        let key = self.key(path.clone());
+        self.produced.insert(key.clone());
+        let up_to_date = self.old.outputs.get(&key) == Some(inputs) && path.is_file();
+        if up_to_date {
+            self.new.outputs.insert(key, inputs.clone());
+        }
+        up_to_date
+    }
+
+    /// Record the output at `path` as generated from `inputs` without problems.
+    pub fn insert(&mut self, path: IPath, inputs: Inputs) {
+        let key = self.key(path);
+        self.produced.insert(key.clone());
+        self.new.outputs.insert(key, inputs);
+    }
+
+    /// Remove outputs of the previous build that were not produced by this one (e.g. because their
+    /// page was removed) and write the new cache. Returns the paths of the removed outputs.
+    ///
+    /// Nothing is removed unless `prune` is set (a failed build can miss outputs only because of its
+    /// errors), such outputs are kept in the cache instead to be removed by a later build.
+    pub fn finish(mut self, prune: bool) -> io::Result<Vec<IPath>> {
+        let mut removed = Vec::new();
+        for (key, inputs) in &self.old.outputs {
+            if self.produced.contains(key) {
+                continue;
+            }
+            if !prune {
+                self.new.outputs.insert(key.clone(), inputs.clone());
+                continue;
+            }
+            let path = self.out_dir.join(key);
+            match fs::remove_file(&path) {
+                Ok(()) => removed.push(path.clone()),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+            // remove directories left empty, failing on the first non-empty one
+            for dir in path.ancestors().skip(1) {
+                if dir == self.out_dir.as_path() || fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+        self.new.write(self.path)?;
+        Ok(removed)
+    }
+}
+
+/// SHA-256 behind the [`Hasher`] interface. Unlike [`std::hash::DefaultHasher`], its results do not
+/// change between Rust releases, so caches stay valid across toolchain updates.
+#[derive(Default)]
+struct StableHasher(Sha256);
+
+impl StableHasher {
+    fn digest(self) -> IString {
+        format!("{:x}", self.0.finalize()).into()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    // lengths are hashed as `usize`, which would otherwise depend on the platform
+    fn write_usize(&mut self, value: usize) {
+        self.write(&(value as u64).to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        u64::from_le_bytes(
+            digest[..8]
+                .try_into()
+                .expect("digest should be 32 bytes long"),
+        )
+    }
+}
+
+/// Hash paths and contents of `files`. Missing files are hashed as empty.
+pub fn hash_files<'a>(files: impl IntoIterator<Item = &'a IPath>) -> IString {
+    let mut hasher = StableHasher::default();
+    for file in files {
+        file.hash(&mut hasher);
+        fs::read(file).unwrap_or_default().hash(&mut hasher);
+    }
+    hasher.digest()
+}
+
+/// Hash a `value` generated from the inputs (instead of the input files themselves).
+pub fn hash_value(value: &impl Hash) -> IString {
+    let mut hasher = StableHasher::default();
+    value.hash(&mut hasher);
+    hasher.digest()
+}
+
+/// Hash everything in the source directory that every output depends on: the languages, i18n and
+/// tags files, all meta files, the list of page files and their front matter, along with `options`
+/// of the run.
+pub fn hash_stores(src_dir: IPath, options: &[&str]) -> IString {
+    let mut files = [src_dir.join("languages.toml"), src_dir.join("i18n.toml")]
+        .into_iter()
+        .filter(|path| path.exists())
+        .chain(iter_deep_or_empty(src_dir.join("tags")))
+        .collect::<Vec<_>>();
+    let mut page_files = Vec::new();
+    for path in iter_deep_or_empty(src_dir.join("pages")) {
+        let (_, pre, _) = split_into_name_pre_ext(path.file_name_lossy().unwrap_or_default());
+        match pre == "meta" {
+            true => files.push(path),
+            false => page_files.push(path),
+        }
+    }
+    files.sort();
+    page_files.sort();
+
+    let mut hasher = StableHasher::default();
+    hash_files(&files).hash(&mut hasher);
+    for path in page_files {
+        // front matter is a part of the metas, the rest of the file is not
+        let source = fs::read_to_string(&path).unwrap_or_default();
+        let front_matter = find_front_matter(&source).map(|front_matter| front_matter.len);
+        (path, &source[..front_matter.unwrap_or_default()]).hash(&mut hasher);
+    }
+    options.hash(&mut hasher);
+    hasher.digest()
+}
+
+/// Version of the engine, including the modification time of its executable, so that rebuilding
+/// the engine with changes (but without a version bump) also invalidates the cache.
+pub fn engine_version() -> IString {
+    let modified = env::current_exe()
+        .and_then(fs::metadata)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_nanos())
+        .unwrap_or_default();
+    format!("{}+{modified:x}", env!("CARGO_PKG_VERSION")).into()
+}
+
+fn iter_deep_or_empty(dir: IPath) -> Box<dyn Iterator<Item = IPath>> {
+    match dir.is_dir() {
+        true => Box::new(iter_deep(dir)),
+        false => Box::new(std::iter::empty()),
+    }
+}
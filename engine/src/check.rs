@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fs;
+
+use comrak::nodes::NodeHtmlBlock;
+use comrak::nodes::NodeLink;
+use comrak::nodes::NodeValue;
+use comrak::parse_document;
+use comrak::Arena;
+use comrak::Options;
+use implicit_clone::sync::IArray;
+use implicit_clone::sync::IString;
+
+use crate::error::Diagnostics;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::error::Location;
+use crate::i18n::I18nStore;
+use crate::language::Language;
+use crate::page::PageStore;
+use crate::render::heading_id;
+use crate::render::is_page_link;
+use crate::sync::path::IPath;
+use crate::sync::path::ToIPath;
+use crate::utils::SpannedTable;
+use crate::utils::TomlSource;
+
+/// Find all i18n keys used like `{{ "key" | i18n: lang }}` (or `i18n_with`, or
+/// `{{ count | i18n_count: "key", lang }}`) in a template source. Returns the byte offsets of the
+/// keys' opening quotes along with the keys.
+pub fn i18n_usages(source: &str) -> impl Iterator<Item = (usize, &str)> + '_ {
+    source.match_indices("i18n").filter_map(|(i, filter)| {
+        let rest = &source[i + filter.len()..];
+        let (key_after, rest) = match rest {
+            _ if rest.starts_with("_count") => (true, &rest["_count".len()..]),
+            _ if rest.starts_with("_with") => (false, &rest["_with".len()..]),
+            _ => (false, rest),
+        };
+        if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        let (start, key) = match key_after {
+            true => {
+                let after = rest.trim_start().strip_prefix(':')?.trim_start();
+                let start = source.len() - after.len();
+                let key = after.strip_prefix('"')?.split('"').next()?;
+                (start, key)
+            }
+            false => {
+                let before = source[..i].trim_end().strip_suffix('|')?;
+                let before = before.trim_end().strip_suffix('"')?;
+                let start = before.rfind('"')?;
+                (start, &before[start + 1..])
+            }
+        };
+        (!key.is_empty() && !key.contains(char::is_whitespace)).then_some((start, key))
+    })
+}
+
+/// Report i18n keys that are used by the template (or page) file at `path`, but are not defined in
+/// the i18n file.
+pub fn check_i18n_usages(path: IPath, i18ns: &I18nStore, diagnostics: &mut Diagnostics) {
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(err) => {
+            diagnostics.push(Error::new(ErrorKind::Io, err.to_string()).with_path(path));
+            return;
+        }
+    };
+    for (offset, key) in i18n_usages(&source) {
+        let key: IString = key.to_string().into();
+        if !i18ns.contains(key.clone()) {
+            diagnostics.push(
+                Error::new(ErrorKind::MissingI18n, format!("missing i18n `{key}`"))
+                    .with_path(path.clone())
+                    .with_location(Location::from_offset(&source, offset)),
+            );
+        }
+    }
+}
+
+/// Link found in a rendered output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub url: String,
+    /// Whether the link is an attribute of raw HTML, which is not rewritten like markdown links.
+    pub html: bool,
+}
+
+/// Anchors and links of a rendered output.
+#[derive(Debug, Clone, Default)]
+pub struct OutputLinks {
+    /// Heading ids and `id` attributes of raw HTML.
+    pub anchors: Vec<String>,
+    pub links: Vec<Link>,
+}
+
+/// Collect anchors and links (of links, images and `href`/`src` attributes) of markdown `content`.
+pub fn collect_links(content: &str, options: &Options) -> OutputLinks {
+    let arena = Arena::new();
+    let root = parse_document(&arena, content, options);
+    let mut output_links = OutputLinks::default();
+    for node in root.descendants() {
+        match node.data.borrow().value {
+            NodeValue::Heading(_) => output_links.anchors.push(heading_id(node)),
+            NodeValue::Link(NodeLink { ref url, .. })
+            | NodeValue::Image(NodeLink { ref url, .. }) => output_links.links.push(Link {
+                url: url.clone(),
+                html: false,
+            }),
+            NodeValue::HtmlBlock(NodeHtmlBlock {
+                literal: ref html, ..
+            })
+            | NodeValue::HtmlInline(ref html) => {
+                let html = strip_elements(html, &["script", "style"]);
+                output_links
+                    .anchors
+                    .extend(attributes(&html, "id").map(str::to_string));
+                output_links.links.extend(
+                    attributes(&html, "href")
+                        .chain(attributes(&html, "src"))
+                        .map(|url| Link {
+                            url: url.to_string(),
+                            html: true,
+                        }),
+                );
+            }
+            _ => {}
+        }
+    }
+    output_links
+}
+
+/// Remove the contents of `elements` (e.g. scripts, whose strings look like attributes).
+fn strip_elements(html: &str, elements: &[&str]) -> String {
+    let mut html = html.to_string();
+    for element in elements {
+        let (open, close) = (format!("<{element}"), format!("</{element}>"));
+        while let Some(start) = html.find(&open) {
+            let end = html[start..]
+                .find(&close)
+                .map_or(html.len(), |end| start + end + close.len());
+            html.replace_range(start..end, "");
+        }
+    }
+    html
+}
+
+/// Find values of all `name="..."` (or `name='...'`) attributes.
+fn attributes<'a>(html: &'a str, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    html.match_indices(name).filter_map(move |(i, _)| {
+        let before = html[..i].chars().next_back()?;
+        if !before.is_whitespace() {
+            return None;
+        }
+        let rest = html[i + name.len()..].trim_start().strip_prefix('=')?;
+        let rest = rest.trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let rest = &rest[1..];
+        Some(&rest[..rest.find(quote)?])
+    })
+}
+
+/// Read `links.toml` of the source directory, listing external URLs (or their prefixes) that are
+/// known to be valid, so that external links can be checked without network access.
+pub fn read_link_allowlist(path: IPath, diagnostics: &mut Diagnostics) -> IArray<IString> {
+    let result = TomlSource::read::<SpannedTable>(path).and_then(|(file, mut table)| {
+        file.take_list(&mut table, "valid")
+            .map(|(allowlist, _)| allowlist)
+    });
+    result.unwrap_or_else(|err| {
+        diagnostics.push(err);
+        IArray::EMPTY
+    })
+}
+
+/// Everything links of outputs are resolved against.
+pub struct LinkCtx<'a> {
+    /// Directory served at the root of the host.
+    pub root_dir: IPath,
+    pub out_dir: IPath,
+    pub base_url: &'a str,
+    pub pages: &'a PageStore,
+    /// Anchors and links of all outputs, by their paths.
+    pub outputs: &'a HashMap<IPath, OutputLinks>,
+    /// External URL prefixes known to be valid, if external links are checked.
+    pub allowlist: Option<IArray<IString>>,
+}
+
+/// Report links of the output at `path` that point to missing pages, files or anchors. Links to
+/// unknown pages are not reported, as they are already reported when rendering.
+pub fn check_links(
+    path: IPath,
+    lang: Language,
+    output_links: &OutputLinks,
+    ctx: &LinkCtx,
+) -> Vec<Error> {
+    let mut errors = Vec::new();
+    for Link { url, html } in &output_links.links {
+        let (target, anchor) = url.split_once('#').unwrap_or((url, ""));
+        let target = target.split_once('?').map_or(target, |(target, _)| target);
+
+        let targets = match target.split_once("://") {
+            _ if target.is_empty() => vec![path.clone()],
+            _ if target.starts_with(ctx.base_url) && !ctx.base_url.is_empty() => {
+                vec![ctx
+                    .out_dir
+                    .join(target[ctx.base_url.len()..].trim_start_matches('/'))]
+            }
+            Some((scheme, _)) => {
+                if let Some(allowlist) = &ctx.allowlist {
+                    if matches!(scheme, "http" | "https")
+                        && !allowlist
+                            .iter()
+                            .any(|valid| url.starts_with(valid.as_str()))
+                    {
+                        errors.push(Error::new(
+                            ErrorKind::UncheckedLink,
+                            format!("external link `{url}` is not listed in `links.toml`"),
+                        ));
+                    }
+                }
+                continue;
+            }
+            None if target.starts_with("//")
+                || !is_page_link(target) && !target.starts_with('/') =>
+            {
+                continue;
+            }
+            None if target.starts_with('/') => {
+                vec![ctx.root_dir.join(target.trim_start_matches('/'))]
+            }
+            None if *html => vec![path.parent().unwrap().join(target).to_ipath()],
+            None => {
+                // same as rendering, pages not available in the language link to all variants
+                let Some(variants) = ctx.pages.get(target.to_ipath()) else {
+                    continue;
+                };
+                let langs = match variants.contains_key(&lang) {
+                    true => vec![lang.clone()],
+                    false => variants.keys().cloned().collect(),
+                };
+                langs
+                    .into_iter()
+                    .map(|lang| ctx.out_dir.join(&*lang.id).join(target))
+                    .collect()
+            }
+        };
+
+        for target in targets {
+            let output = [target.join("index.html"), target.clone()]
+                .into_iter()
+                .find_map(|target| ctx.outputs.get(&target));
+            match output {
+                Some(output)
+                    if !anchor.is_empty() && !output.anchors.iter().any(|a| a == anchor) =>
+                {
+                    errors.push(Error::new(
+                        ErrorKind::BrokenLink,
+                        format!("link to unknown anchor `{url}`"),
+                    ))
+                }
+                Some(_) => {}
+                None if target.exists() => {}
+                None => errors.push(Error::new(
+                    ErrorKind::BrokenLink,
+                    format!("link to missing file `{url}`"),
+                )),
+            }
+        }
+    }
+    errors
+}
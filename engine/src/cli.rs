@@ -0,0 +1,156 @@
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread;
+
+use clap::ArgAction;
+use clap::Parser;
+use clap::Subcommand;
+
+use crate::utils::is_absolute_url;
+
+/// Static site generator of SecDB.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Cli {
+    #[command(flatten)]
+    pub args: Args,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Arguments shared by all commands.
+#[derive(Debug, Clone, clap::Args)]
+pub struct Args {
+    /// Source directory containing `languages.toml`, `i18n.toml`, `tags`, `pages` and `templates`.
+    #[arg(short, long, global = true, default_value = "secdb")]
+    pub src: PathBuf,
+
+    /// Output directory the website is generated into.
+    #[arg(short, long, global = true, default_value = "public/secdb")]
+    pub out: PathBuf,
+
+    /// URL prefix the output directory is served under (e.g. `/secdb` or `https://a.b/secdb`).
+    #[arg(short, long, global = true, default_value = "/secdb")]
+    pub base_url: String,
+
+    /// File the build cache is kept in (outside of the output directory, so it is not deployed).
+    #[arg(long, global = true, default_value = "target/engine-cache.toml")]
+    pub cache: PathBuf,
+
+    /// Number of threads to render outputs with (defaults to the number of CPUs).
+    #[arg(short, long, global = true)]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// Print more information (can be repeated).
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Render all outputs, even those the build cache considers up to date.
+    #[arg(short, long, global = true)]
+    pub force: bool,
+
+    /// Include draft pages, marked with a banner (always included by `serve`).
+    #[arg(long, global = true)]
+    pub drafts: bool,
+
+    /// Print only errors and warnings.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+}
+
+impl Args {
+    /// `0` when quiet, `1` by default, `2+` when verbose.
+    pub fn verbosity(&self) -> u8 {
+        match self.quiet {
+            true => 0,
+            false => 1 + self.verbose,
+        }
+    }
+
+    /// Number of render threads, see [`Args::jobs`].
+    pub fn jobs(&self) -> usize {
+        self.jobs
+            .or_else(|| thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get)
+    }
+
+    /// Base URL without the trailing slash, so it can be directly followed by `/<lang>/...`.
+    pub fn base_url(&self) -> &str {
+        self.base_url.trim_end_matches('/')
+    }
+
+    /// Whether the [`Args::base_url`] has a scheme (e.g. `https://a.b/secdb`), as needed by outputs
+    /// read outside of the website, like feeds.
+    pub fn is_base_url_absolute(&self) -> bool {
+        is_absolute_url(&self.base_url)
+    }
+
+    /// Path part of the [`Args::base_url`] (e.g. `/secdb` for `https://a.b/secdb`).
+    pub fn base_path(&self) -> &str {
+        let base_url = self.base_url();
+        match base_url.split_once("://") {
+            Some((_, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or(""),
+            None => base_url,
+        }
+    }
+
+    /// Directory served at the root of the host, which is the [`Args::out`] directory without the
+    /// [`Args::base_path`] (e.g. `public` for `public/secdb` served under `/secdb`). `None` if the
+    /// output directory does not end with the base path.
+    pub fn root_dir(&self) -> Option<PathBuf> {
+        let base_path = Path::new(self.base_path().trim_matches('/'));
+        match self.out.ends_with(base_path) {
+            true => Some(
+                self.out
+                    .ancestors()
+                    .nth(base_path.components().count())?
+                    .into(),
+            ),
+            false => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Subcommand)]
+pub enum Command {
+    /// Generate the website into the output directory (default).
+    #[default]
+    Build,
+    /// Run the whole pipeline without writing anything and report all problems.
+    Check(CheckArgs),
+    /// Remove the output directory.
+    Clean,
+    /// Report what is not translated to every language other than the default one.
+    I18nStatus(I18nStatusArgs),
+    /// List languages, i18ns, tags and pages of the source directory.
+    List,
+    /// Build, serve the output directory locally and rebuild on changes of the source directory.
+    Serve(ServeArgs),
+}
+
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct CheckArgs {
+    /// Also check that external links are listed in `links.toml` of the source directory.
+    #[arg(long)]
+    pub external: bool,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct I18nStatusArgs {
+    /// Also write the report as an HTML page to the given file.
+    #[arg(long)]
+    pub html: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ServeArgs {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: String,
+
+    /// Port to listen on.
+    #[arg(short, long, default_value_t = 8000)]
+    pub port: u16,
+}
@@ -0,0 +1,277 @@
+use std::ops::RangeInclusive;
+
+use itertools::Itertools;
+
+/// Languages of code blocks whose lines starting with `$ ` are commands after a prompt.
+pub const SHELL_LANGUAGES: [&str; 4] = ["bash", "sh", "shell", "zsh"];
+
+/// Languages of code blocks with terminal sessions: commands after prompts (e.g. `$ `, `# ` or
+/// `user@host:~$ `), highlighted as `bash`, and their output.
+pub const CONSOLE_LANGUAGES: [&str; 2] = ["console", "shell-session"];
+
+/// Settings of a code block from its info string, e.g. `bash title="extract.sh" {3,5-7} linenos`:
+/// the language, then any of a `title` (e.g. the file name), ranges of lines to highlight in
+/// braces, `linenos` to number the lines and `diff` for lines marked with `+`/`-`. Other
+/// attributes (e.g. ones of other renderers) are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeInfo {
+    pub lang: String,
+    pub title: Option<String>,
+    /// Ranges of highlighted lines, numbered from `1`.
+    pub highlighted: Vec<RangeInclusive<usize>>,
+    pub line_numbers: bool,
+    /// Lines start with a `+` (added), `-` (removed) or ` ` (unchanged) marker.
+    pub diff: bool,
+}
+
+/// Part of a line before its code, which is not highlighted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prefix {
+    /// Prompt of a shell command, which is not copied.
+    Prompt,
+    /// `+`, `-` or ` ` of a diff line.
+    DiffMarker,
+}
+
+impl Prefix {
+    pub fn class(self) -> &'static str {
+        match self {
+            Prefix::Prompt => "prompt",
+            Prefix::DiffMarker => "diff-marker",
+        }
+    }
+}
+
+/// Line of a code block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line<'a> {
+    /// Number of the line, from `1`.
+    pub number: usize,
+    pub prefix: Option<(Prefix, &'a str)>,
+    pub code: &'a str,
+    /// Output of a command in a terminal session, which is not highlighted.
+    pub output: bool,
+    /// Classes of the line (`line` and any of `highlighted`, `added`, `removed` and `output`).
+    pub classes: Vec<&'static str>,
+}
+
+impl CodeInfo {
+    pub fn parse(info: &str) -> Result<CodeInfo, String> {
+        let mut chars = info.trim().chars().peekable();
+        let mut code_info = CodeInfo {
+            lang: chars
+                .by_ref()
+                .peeking_take_while(|c| !c.is_whitespace())
+                .collect(),
+            ..CodeInfo::default()
+        };
+        let mut keys = Vec::new();
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.peek().is_none() {
+                break;
+            }
+            if chars.next_if_eq(&'{').is_some() {
+                let ranges: String = chars.by_ref().peeking_take_while(|c| *c != '}').collect();
+                if chars.next().is_none() {
+                    return Err("unterminated `{`".to_owned());
+                }
+                code_info.highlighted.extend(parse_ranges(&ranges)?);
+                continue;
+            }
+            let key: String = chars
+                .by_ref()
+                .peeking_take_while(|c| !c.is_whitespace() && *c != '=')
+                .collect();
+            let value = match chars.next_if_eq(&'=') {
+                Some(_) if chars.next_if_eq(&'"').is_some() => {
+                    let value: String = chars.by_ref().peeking_take_while(|c| *c != '"').collect();
+                    if chars.next().is_none() {
+                        return Err(format!("unterminated quote in the value of `{key}`"));
+                    }
+                    Some(value)
+                }
+                Some(_) => Some(
+                    chars
+                        .by_ref()
+                        .peeking_take_while(|c| !c.is_whitespace())
+                        .collect(),
+                ),
+                None => None,
+            };
+            if ["title", "linenos", "diff"].contains(&key.as_str()) {
+                if keys.contains(&key) {
+                    return Err(format!("`{key}` should be set only once"));
+                }
+                keys.push(key.clone());
+            }
+            match (key.as_str(), value) {
+                ("title", Some(title)) => code_info.title = Some(title),
+                ("linenos", None) => code_info.line_numbers = true,
+                ("diff", None) => code_info.diff = true,
+                ("title", None) => return Err("`title` should have a value".to_owned()),
+                ("linenos" | "diff", Some(_)) => {
+                    return Err(format!("`{key}` should not have a value"))
+                }
+                _ => {}
+            }
+        }
+        Ok(code_info)
+    }
+
+    /// Whether the lines of the code have to be rendered one by one, as opposed to the whole code
+    /// at once.
+    pub fn has_line_features(&self, code: &str) -> bool {
+        !self.highlighted.is_empty()
+            || self.line_numbers
+            || self.diff
+            || self.lang == "diff"
+            || self.is_console()
+            || self.lines(code).iter().any(|line| line.prefix.is_some())
+    }
+
+    /// Whether the code is a terminal session, see [`CONSOLE_LANGUAGES`].
+    pub fn is_console(&self) -> bool {
+        CONSOLE_LANGUAGES.contains(&self.lang.as_str())
+    }
+
+    /// Language the code is highlighted in.
+    pub fn highlight_lang(&self) -> &str {
+        match self.is_console() {
+            true => "bash",
+            false => &self.lang,
+        }
+    }
+
+    /// Lines of the code, split into their prefixes and code.
+    pub fn lines<'a>(&self, code: &'a str) -> Vec<Line<'a>> {
+        // whether the previous command is continued on the line after a `\`
+        let mut continued = false;
+        code.lines()
+            .enumerate()
+            .map(|(index, text)| {
+                let number = index + 1;
+                let mut classes = vec!["line"];
+                if self.highlighted.iter().any(|range| range.contains(&number)) {
+                    classes.push("highlighted");
+                }
+                let mut output = false;
+                let (prefix, code) = if self.diff {
+                    let marker_len = text.chars().next().map_or(0, char::len_utf8);
+                    let (marker, code) = text.split_at(marker_len);
+                    (Some((Prefix::DiffMarker, marker)), code)
+                } else if self.is_console() {
+                    let prompt = split_prompt(text).filter(|_| !continued);
+                    output = prompt.is_none() && !continued;
+                    continued = !output && text.ends_with('\\');
+                    match prompt {
+                        Some((prompt, command)) => (Some((Prefix::Prompt, prompt)), command),
+                        None => (None, text),
+                    }
+                } else if SHELL_LANGUAGES.contains(&self.lang.as_str()) && text.starts_with("$ ") {
+                    (Some((Prefix::Prompt, &text[..2])), &text[2..])
+                } else {
+                    (None, text)
+                };
+                if output {
+                    classes.push("output");
+                }
+                let marker = match (self.diff, self.lang.as_str()) {
+                    (true, _) => prefix.map(|(_, marker)| marker),
+                    (false, "diff") if !text.starts_with("+++") && !text.starts_with("---") => {
+                        text.get(..1)
+                    }
+                    _ => None,
+                };
+                match marker {
+                    Some("+") => classes.push("added"),
+                    Some("-") => classes.push("removed"),
+                    _ => {}
+                }
+                Line {
+                    number,
+                    prefix,
+                    code,
+                    output,
+                    classes,
+                }
+            })
+            .collect()
+    }
+
+    /// Text copied by the copy button of the code: the code without prompts and output, or the
+    /// resulting code of a diff (without its markers and removed lines).
+    pub fn copy_text(&self, code: &str) -> String {
+        self.lines(code)
+            .iter()
+            .filter(|line| !line.output)
+            .filter(|line| !(self.diff && line.classes.contains(&"removed")))
+            .map(|line| line.code)
+            .join("\n")
+    }
+}
+
+/// Split a line of a terminal session into its prompt, a bare `$`, `#` or `%` or one after the user
+/// and host (e.g. `root@kali:~# `), along with the space after it, and the command.
+fn split_prompt(text: &str) -> Option<(&str, &str)> {
+    let (prompt, _) = text.split_once(' ')?;
+    let is_prompt =
+        prompt.ends_with(['$', '#', '%']) && (prompt.len() == 1 || prompt.contains('@'));
+    is_prompt.then(|| text.split_at(prompt.len() + 1))
+}
+
+/// Ranges of lines like `3,5-7`.
+fn parse_ranges(ranges: &str) -> Result<Vec<RangeInclusive<usize>>, String> {
+    ranges
+        .split(',')
+        .map(str::trim)
+        .map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            match (start.trim().parse::<usize>(), end.trim().parse::<usize>()) {
+                (Ok(start), Ok(end)) if 1 <= start && start <= end => Ok(start..=end),
+                _ => Err(format!(
+                    "invalid line range `{range}`, expected a line number like `3` or a range \
+                     like `5-7`"
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Split HTML of highlighted code into lines, closing the tags open at the end of every line and
+/// opening them again in the next one.
+pub fn split_highlighted_lines(html: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut open: Vec<&str> = Vec::new();
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' => {
+                let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+                let tag = &rest[..end];
+                if tag.starts_with("</") {
+                    open.pop();
+                } else if !tag.ends_with("/>") {
+                    open.push(tag);
+                }
+                line.push_str(tag);
+                rest = &rest[end..];
+            }
+            '\n' => {
+                for tag in open.iter().rev() {
+                    let name = tag[1..].split([' ', '>']).next().unwrap_or_default();
+                    line.push_str(&format!("</{name}>"));
+                }
+                lines.push(std::mem::replace(&mut line, open.concat()));
+                rest = &rest[1..];
+            }
+            c => {
+                line.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    lines.push(line);
+    lines
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use implicit_clone::ImplicitClone;
+use toml::value::Datetime;
+use toml::value::Offset;
+
+/// Point in time, in seconds since the Unix epoch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Date(pub i64);
+
+impl ImplicitClone for Date {}
+
+impl Date {
+    /// Convert a TOML date (e.g. `2025-01-31` or `2025-01-31T12:00:00+03:00`). Missing time is
+    /// midnight, missing offset is UTC. Returns `None` for a time without a date.
+    pub fn from_toml(datetime: &Datetime) -> Option<Date> {
+        let date = datetime.date?;
+        let (hour, minute, second) = datetime
+            .time
+            .map(|time| (time.hour, time.minute, time.second))
+            .unwrap_or_default();
+        let offset_minutes = match datetime.offset {
+            Some(Offset::Custom { minutes }) => minutes.into(),
+            Some(Offset::Z) | None => 0,
+        };
+        let days = days_from_civil(date.year.into(), date.month.into(), date.day.into());
+        Some(Date(
+            days * 86400 + i64::from(hour) * 3600 + i64::from(minute) * 60 + i64::from(second)
+                - offset_minutes * 60,
+        ))
+    }
+
+    pub fn from_system_time(time: SystemTime) -> Date {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => Date(after.as_secs() as i64),
+            Err(before) => Date(-(before.duration().as_secs() as i64)),
+        }
+    }
+}
+
+impl Date {
+    /// Format the date in UTC, replacing `%Y` (year), `%m` and `%d` (zero-padded month and day),
+    /// `%-m` and `%-d` (month and day), `%H` and `%M` (hour and minute) and `%%` (`%`) in
+    /// `format`. Other characters are kept as they are.
+    pub fn format(&self, format: &str) -> String {
+        let (days, seconds) = (self.0.div_euclid(86400), self.0.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+        let mut output = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                output.push(c);
+                continue;
+            }
+            let rest = chars.as_str();
+            let (formatted, len) = match rest.get(..2).filter(|s| s.starts_with('-')) {
+                Some("-m") => (month.to_string(), 2),
+                Some("-d") => (day.to_string(), 2),
+                _ => match rest.chars().next() {
+                    Some('Y') => (format!("{year:04}"), 1),
+                    Some('m') => (format!("{month:02}"), 1),
+                    Some('d') => (format!("{day:02}"), 1),
+                    Some('H') => (format!("{:02}", seconds / 3600), 1),
+                    Some('M') => (format!("{:02}", seconds / 60 % 60), 1),
+                    Some('%') => ("%".to_owned(), 1),
+                    _ => ("%".to_owned(), 0),
+                },
+            };
+            output.push_str(&formatted);
+            chars = rest[len..].chars();
+        }
+        output
+    }
+}
+
+impl Display for Date {
+    /// Formats as RFC 3339 in UTC (e.g. `2025-01-31T12:00:00Z`).
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (days, seconds) = (self.0.div_euclid(86400), self.0.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+        write!(
+            f,
+            "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+}
+
+/// Days since the Unix epoch of a proleptic Gregorian calendar date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Proleptic Gregorian calendar date of days since the Unix epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
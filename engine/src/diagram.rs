@@ -0,0 +1,1370 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use itertools::Itertools;
+
+use crate::utils::escape_xml;
+
+const FONT_SIZE: f64 = 14.0;
+/// Estimated average width of a character of the font.
+const CHAR_WIDTH: f64 = 8.0;
+const LINE_HEIGHT: f64 = 18.0;
+const PADDING_X: f64 = 12.0;
+const PADDING_Y: f64 = 8.0;
+/// Space between nodes of the same rank.
+const NODE_GAP: f64 = 24.0;
+/// Space between ranks.
+const RANK_GAP: f64 = 40.0;
+const MARGIN: f64 = 8.0;
+const ARROW_LENGTH: f64 = 10.0;
+const ARROW_WIDTH: f64 = 4.0;
+/// How far self loops go out of their node.
+const LOOP_SIZE: f64 = 24.0;
+
+/// Render a graph in a subset of the Graphviz DOT language into an inline SVG, laid out in ranks
+/// like `dot` does.
+///
+/// Supported are `graph`/`digraph`, node and edge statements (with edge chains and subgraphs as
+/// their ends), `node`/`edge`/`graph` default attributes, subgraphs and `rank=same`. The used
+/// attributes are `rankdir` and `label` of the graph, `label`, `shape` (`box`, `ellipse`,
+/// `circle`, `diamond`, `plaintext`), `style` (`dashed`, `dotted`, `bold`, `rounded`, `filled`,
+/// `invis`), `color`, `fillcolor` and `fontcolor` of nodes, and `label`, `style`, `dir` and `color`
+/// of edges. Other attributes are ignored.
+pub fn dot_to_svg(source: &str) -> Result<String, String> {
+    let graph = Parser::new(source)?.graph()?;
+    Ok(render(&graph, &layout(&graph)?))
+}
+
+//
+// parsing
+//
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Identifier, along with whether it is quoted (keywords are not).
+    Id(String, bool),
+    /// `->` or `--`.
+    EdgeOp(&'static str),
+    Punct(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars = source.chars().collect_vec();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    let mut line_start = true;
+    while let Some(&c) = chars.get(pos) {
+        let next = chars.get(pos + 1).copied();
+        match c {
+            '\n' => {
+                line_start = true;
+                pos += 1;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                pos += 1;
+                continue;
+            }
+            '#' if line_start => {
+                while chars.get(pos).is_some_and(|c| *c != '\n') {
+                    pos += 1;
+                }
+            }
+            '/' if next == Some('/') => {
+                while chars.get(pos).is_some_and(|c| *c != '\n') {
+                    pos += 1;
+                }
+            }
+            '/' if next == Some('*') => {
+                pos += 2;
+                while !(chars.get(pos) == Some(&'*') && chars.get(pos + 1) == Some(&'/')) {
+                    if pos >= chars.len() {
+                        return Err("unterminated comment".to_owned());
+                    }
+                    pos += 1;
+                }
+                pos += 2;
+            }
+            '"' => {
+                let mut id = String::new();
+                pos += 1;
+                loop {
+                    match chars.get(pos) {
+                        None => return Err("unterminated string".to_owned()),
+                        Some('"') => break,
+                        Some('\\') if chars.get(pos + 1) == Some(&'"') => {
+                            id.push('"');
+                            pos += 1;
+                        }
+                        Some(&c) => id.push(c),
+                    }
+                    pos += 1;
+                }
+                pos += 1;
+                tokens.push(Token::Id(id, true));
+            }
+            '<' => return Err("HTML labels are not supported".to_owned()),
+            '-' if next == Some('>') => {
+                tokens.push(Token::EdgeOp("->"));
+                pos += 2;
+            }
+            '-' if next == Some('-') => {
+                tokens.push(Token::EdgeOp("--"));
+                pos += 2;
+            }
+            '{' | '}' | '[' | ']' | ';' | ',' | '=' | ':' => {
+                tokens.push(Token::Punct(c));
+                pos += 1;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = pos;
+                while chars
+                    .get(pos)
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+                {
+                    pos += 1;
+                }
+                tokens.push(Token::Id(chars[start..pos].iter().collect(), false));
+            }
+            c if c.is_ascii_digit() || c == '.' || c == '-' => {
+                let start = pos;
+                pos += 1;
+                while chars
+                    .get(pos)
+                    .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+                {
+                    pos += 1;
+                }
+                tokens.push(Token::Id(chars[start..pos].iter().collect(), true));
+            }
+            c => return Err(format!("unexpected `{c}`")),
+        }
+        line_start = false;
+    }
+    Ok(tokens)
+}
+
+type Attrs = BTreeMap<String, String>;
+
+#[derive(Debug, Clone, Default)]
+struct Graph {
+    directed: bool,
+    attrs: Attrs,
+    /// Nodes by their ids, in the order they are first mentioned.
+    nodes: Vec<(String, Attrs)>,
+    /// Edges between nodes by their indices.
+    edges: Vec<(usize, usize, Attrs)>,
+    /// Groups of nodes that have the same rank.
+    same_ranks: Vec<Vec<usize>>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    graph: Graph,
+    node_defaults: Attrs,
+    edge_defaults: Attrs,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Parser, String> {
+        Ok(Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            graph: Graph::default(),
+            node_defaults: Attrs::new(),
+            edge_defaults: Attrs::new(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let eaten = self.peek() == Some(&Token::Punct(c));
+        if eaten {
+            self.pos += 1;
+        }
+        eaten
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(format!("expected `{c}`{}", self.found())),
+        }
+    }
+
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(Token::Id(id, _)) => format!(", found `{id}`"),
+            Some(Token::EdgeOp(op)) => format!(", found `{op}`"),
+            Some(Token::Punct(c)) => format!(", found `{c}`"),
+            None => ", found the end".to_owned(),
+        }
+    }
+
+    /// Consume the keyword `keyword` if it is next.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(
+            self.peek(),
+            Some(Token::Id(id, false)) if id.eq_ignore_ascii_case(keyword)
+        );
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn id(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Id(id, _)) => {
+                let id = id.clone();
+                self.pos += 1;
+                Ok(id)
+            }
+            _ => Err(format!("expected an identifier{}", self.found())),
+        }
+    }
+
+    fn graph(mut self) -> Result<Graph, String> {
+        self.keyword("strict");
+        self.graph.directed = match () {
+            () if self.keyword("digraph") => true,
+            () if self.keyword("graph") => false,
+            () => return Err(format!("expected `graph` or `digraph`{}", self.found())),
+        };
+        if let Some(Token::Id(..)) = self.peek() {
+            self.pos += 1;
+        }
+        self.expect('{')?;
+        self.statements(true)?;
+        self.expect('}')?;
+        match self.peek() {
+            Some(_) => Err(format!("expected the end{}", self.found())),
+            None => Ok(self.graph),
+        }
+    }
+
+    /// Parse statements until `}`, which is not consumed. Returns the nodes mentioned in them.
+    fn statements(&mut self, root: bool) -> Result<Vec<usize>, String> {
+        let mut nodes = Vec::new();
+        let mut same_rank = false;
+        while !matches!(self.peek(), Some(Token::Punct('}')) | None) {
+            if self.eat(';') {
+                continue;
+            }
+            if self.keyword("node") {
+                let attrs = self.attr_list()?;
+                self.node_defaults.extend(attrs);
+            } else if self.keyword("edge") {
+                let attrs = self.attr_list()?;
+                self.edge_defaults.extend(attrs);
+            } else if self.keyword("graph") {
+                let attrs = self.attr_list()?;
+                same_rank |= self.graph_attrs(attrs, root)?;
+            } else if matches!(self.tokens.get(self.pos + 1), Some(Token::Punct('=')))
+                && matches!(self.peek(), Some(Token::Id(..)))
+            {
+                let name = self.id()?;
+                self.pos += 1;
+                let value = self.id()?;
+                same_rank |= self.graph_attrs([(name, value)].into(), root)?;
+            } else {
+                nodes.extend(self.edge_statement()?);
+            }
+        }
+        if same_rank {
+            self.graph.same_ranks.push(nodes.clone());
+        }
+        Ok(nodes)
+    }
+
+    /// Set attributes of the graph (or of a subgraph, which only supports `rank`). Returns whether
+    /// the nodes have the same rank.
+    fn graph_attrs(&mut self, attrs: Attrs, root: bool) -> Result<bool, String> {
+        let mut same_rank = false;
+        for (name, value) in attrs {
+            match name.as_str() {
+                "rank" if value == "same" => same_rank = true,
+                "rank" => return Err(format!("unsupported rank `{value}`, expected `same`")),
+                _ if root => {
+                    self.graph.attrs.insert(name, value);
+                }
+                _ => {}
+            }
+        }
+        Ok(same_rank)
+    }
+
+    fn attr_list(&mut self) -> Result<Attrs, String> {
+        let mut attrs = Attrs::new();
+        while self.eat('[') {
+            while !self.eat(']') {
+                let name = self.id()?;
+                self.expect('=')?;
+                let value = self.id()?;
+                attrs.insert(name, value);
+                if !self.eat(',') {
+                    self.eat(';');
+                }
+            }
+        }
+        Ok(attrs)
+    }
+
+    /// Parse a node statement or a chain of edges. Returns the nodes mentioned in it.
+    fn edge_statement(&mut self) -> Result<Vec<usize>, String> {
+        let (first, is_node) = self.node_or_subgraph()?;
+        let mut ends = vec![first];
+        while let Some(&Token::EdgeOp(op)) = self.peek() {
+            let expected = if self.graph.directed { "->" } else { "--" };
+            if op != expected {
+                return Err(format!("expected `{expected}` in this graph, found `{op}`"));
+            }
+            self.pos += 1;
+            ends.push(self.node_or_subgraph()?.0);
+        }
+        let attrs = self.attr_list()?;
+        if ends.len() == 1 {
+            if is_node {
+                let node = ends[0][0];
+                self.graph.nodes[node].1.extend(attrs);
+            }
+            return Ok(ends.concat());
+        }
+        for (from, to) in ends.iter().tuple_windows() {
+            for (&from, &to) in from.iter().cartesian_product(to) {
+                let mut edge_attrs = self.edge_defaults.clone();
+                edge_attrs.extend(attrs.clone());
+                self.graph.edges.push((from, to, edge_attrs));
+            }
+        }
+        Ok(ends.concat())
+    }
+
+    /// Parse a node (ignoring its port) or a subgraph. Returns the nodes along with whether it is
+    /// a node.
+    fn node_or_subgraph(&mut self) -> Result<(Vec<usize>, bool), String> {
+        let subgraph = self.keyword("subgraph");
+        if subgraph && matches!(self.peek(), Some(Token::Id(..))) {
+            self.pos += 1;
+        }
+        if subgraph || matches!(self.peek(), Some(Token::Punct('{'))) {
+            self.expect('{')?;
+            let node_defaults = self.node_defaults.clone();
+            let edge_defaults = self.edge_defaults.clone();
+            let nodes = self.statements(false)?;
+            self.expect('}')?;
+            self.node_defaults = node_defaults;
+            self.edge_defaults = edge_defaults;
+            return Ok((nodes, false));
+        }
+        let id = self.id()?;
+        // port and compass point
+        while self.eat(':') {
+            self.id()?;
+        }
+        let node = match self.graph.nodes.iter().position(|(node, _)| *node == id) {
+            Some(node) => node,
+            None => {
+                self.graph.nodes.push((id, self.node_defaults.clone()));
+                self.graph.nodes.len() - 1
+            }
+        };
+        Ok((vec![node], true))
+    }
+}
+
+//
+// layout
+//
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Box,
+    Ellipse,
+    Circle,
+    Diamond,
+    Plaintext,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RankDir {
+    TopBottom,
+    BottomTop,
+    LeftRight,
+    RightLeft,
+}
+
+impl RankDir {
+    /// Whether ranks go along the horizontal axis.
+    fn horizontal(self) -> bool {
+        matches!(self, RankDir::LeftRight | RankDir::RightLeft)
+    }
+}
+
+/// Drawing style of a node or an edge.
+#[derive(Debug, Clone, Default)]
+struct Style {
+    dashed: bool,
+    dotted: bool,
+    bold: bool,
+    rounded: bool,
+    filled: bool,
+    invisible: bool,
+    color: Option<String>,
+    fill_color: Option<String>,
+    font_color: Option<String>,
+}
+
+impl Style {
+    fn new(attrs: &Attrs) -> Result<Style, String> {
+        let mut style = Style::default();
+        for name in attrs
+            .get("style")
+            .into_iter()
+            .flat_map(|style| style.split(','))
+            .map(str::trim)
+        {
+            match name {
+                "dashed" => style.dashed = true,
+                "dotted" => style.dotted = true,
+                "bold" => style.bold = true,
+                "rounded" => style.rounded = true,
+                "filled" => style.filled = true,
+                "invis" => style.invisible = true,
+                "solid" | "" => {}
+                name => return Err(format!("unsupported style `{name}`")),
+            }
+        }
+        style.color = color(attrs, "color")?;
+        style.fill_color = color(attrs, "fillcolor")?;
+        style.font_color = color(attrs, "fontcolor")?;
+        Ok(style)
+    }
+
+    /// SVG attributes of the stroke.
+    fn stroke(&self) -> String {
+        let mut attrs = format!(
+            r#" stroke="{}""#,
+            self.color.as_deref().unwrap_or("currentColor")
+        );
+        if self.bold {
+            attrs.push_str(r#" stroke-width="2""#);
+        }
+        if self.dashed {
+            attrs.push_str(r#" stroke-dasharray="5,3""#);
+        } else if self.dotted {
+            attrs.push_str(r#" stroke-dasharray="1,3""#);
+        }
+        attrs
+    }
+}
+
+/// Color attribute `name`: a name or a `#` hex color.
+fn color(attrs: &Attrs, name: &str) -> Result<Option<String>, String> {
+    match attrs.get(name) {
+        Some(color)
+            if color
+                .strip_prefix('#')
+                .unwrap_or(color)
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric())
+                && !color.is_empty() =>
+        {
+            Ok(Some(color.clone()))
+        }
+        Some(color) => Err(format!("invalid {name} `{color}`")),
+        None => Ok(None),
+    }
+}
+
+/// Lines of a label, with the `\n`, `\l` and `\r` line breaks and `\N` (id of the node) of DOT.
+fn label_lines(label: &str, id: &str) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    let mut chars = label.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'l' | 'r') => lines.push(String::new()),
+                Some('N') => lines.last_mut().unwrap().push_str(id),
+                Some(c) => lines.last_mut().unwrap().push(c),
+                None => lines.last_mut().unwrap().push('\\'),
+            },
+            c => lines.last_mut().unwrap().push(c),
+        }
+    }
+    if lines.len() > 1 && lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Size of the text of `lines`.
+fn text_size(lines: &[String]) -> (f64, f64) {
+    let width = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or_default() as f64
+        * CHAR_WIDTH;
+    (width, lines.len() as f64 * LINE_HEIGHT)
+}
+
+#[derive(Debug, Clone)]
+struct NodeLayout {
+    lines: Vec<String>,
+    shape: Shape,
+    style: Style,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl NodeLayout {
+    /// Point of the border of the node on the way from its center to `(x, y)`.
+    fn clip(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (dx, dy) = (x - self.x, y - self.y);
+        if dx == 0.0 && dy == 0.0 {
+            return (x, y);
+        }
+        let (rx, ry) = (self.width / 2.0, self.height / 2.0);
+        let t = match self.shape {
+            Shape::Box | Shape::Plaintext => (rx / dx.abs()).min(ry / dy.abs()),
+            Shape::Ellipse | Shape::Circle => 1.0 / ((dx / rx).powi(2) + (dy / ry).powi(2)).sqrt(),
+            Shape::Diamond => 1.0 / (dx.abs() / rx + dy.abs() / ry),
+        };
+        let t = t.min(1.0);
+        (self.x + dx * t, self.y + dy * t)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EdgeLayout {
+    /// Points of the edge from its start to its end.
+    points: Vec<(f64, f64)>,
+    /// Whether the edge loops back to its node.
+    self_loop: bool,
+    label: Option<Vec<String>>,
+    style: Style,
+    arrow_head: bool,
+    arrow_tail: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Layout {
+    nodes: Vec<NodeLayout>,
+    edges: Vec<EdgeLayout>,
+    rank_dir: RankDir,
+    width: f64,
+    height: f64,
+}
+
+/// Vertex of the layered graph: a node, or a point that an edge spanning several ranks goes
+/// through.
+#[derive(Debug, Clone)]
+struct Vertex {
+    node: Option<usize>,
+    rank: usize,
+    /// Size across the ranks.
+    cross: f64,
+    /// Size along the ranks.
+    along: f64,
+}
+
+fn layout(graph: &Graph) -> Result<Layout, String> {
+    let rank_dir = match graph.attrs.get("rankdir").map(String::as_str) {
+        None | Some("TB") => RankDir::TopBottom,
+        Some("BT") => RankDir::BottomTop,
+        Some("LR") => RankDir::LeftRight,
+        Some("RL") => RankDir::RightLeft,
+        Some(rank_dir) => {
+            return Err(format!(
+                "unsupported rankdir `{rank_dir}`, expected `TB`, `BT`, `LR` or `RL`"
+            ))
+        }
+    };
+    let mut nodes = graph
+        .nodes
+        .iter()
+        .map(|(id, attrs)| {
+            let lines = label_lines(attrs.get("label").unwrap_or(id), id);
+            let shape = match attrs.get("shape").map(String::as_str) {
+                None | Some("ellipse" | "oval") => Shape::Ellipse,
+                Some("box" | "rect" | "rectangle" | "square") => Shape::Box,
+                Some("circle") => Shape::Circle,
+                Some("diamond") => Shape::Diamond,
+                Some("plaintext" | "plain" | "none") => Shape::Plaintext,
+                Some(shape) => {
+                    return Err(format!(
+                        "unsupported shape `{shape}`, expected `box`, `ellipse`, `circle`, \
+                         `diamond` or `plaintext`"
+                    ))
+                }
+            };
+            let (text_width, text_height) = text_size(&lines);
+            let (width, height) = match shape {
+                Shape::Box => (text_width + 2.0 * PADDING_X, text_height + 2.0 * PADDING_Y),
+                Shape::Ellipse => (text_width * 1.42 + 16.0, text_height * 1.42 + 8.0),
+                Shape::Circle => {
+                    let size = text_width.max(text_height) * 1.42 + 16.0;
+                    (size, size)
+                }
+                Shape::Diamond => (text_width * 2.0 + 16.0, text_height * 2.0 + 8.0),
+                Shape::Plaintext => (text_width + 8.0, text_height + 4.0),
+            };
+            Ok(NodeLayout {
+                lines,
+                shape,
+                style: Style::new(attrs)?,
+                x: 0.0,
+                y: 0.0,
+                width,
+                height,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    // nodes with the same rank are ranked as one node, their group
+    let mut group = (0..nodes.len()).collect_vec();
+    for same_rank in &graph.same_ranks {
+        if let Some(&first) = same_rank.first() {
+            let target = group[first];
+            for &node in same_rank {
+                let source = group[node];
+                group.iter_mut().for_each(|g| {
+                    if *g == source {
+                        *g = target
+                    }
+                });
+            }
+        }
+    }
+    let ranked_edges = graph
+        .edges
+        .iter()
+        .map(|&(from, to, _)| (group[from], group[to]))
+        .filter(|(from, to)| from != to)
+        .collect_vec();
+    let ranks = rank(nodes.len(), &ranked_edges);
+    let rank_of = |node: usize| ranks[group[node]];
+
+    // layered graph, with edges spanning several ranks going through vertices on each of them
+    let mut vertices = nodes
+        .iter()
+        .enumerate()
+        .map(|(node, layout)| {
+            let (cross, along) = match rank_dir.horizontal() {
+                true => (layout.height, layout.width),
+                false => (layout.width, layout.height),
+            };
+            Vertex {
+                node: Some(node),
+                rank: rank_of(node),
+                cross,
+                along,
+            }
+        })
+        .collect_vec();
+    // vertices of every edge from its start to its end
+    let mut edge_vertices = Vec::new();
+    let mut links = Vec::new();
+    for &(from, to, _) in &graph.edges {
+        let (upper, lower) = match rank_of(from) <= rank_of(to) {
+            true => (from, to),
+            false => (to, from),
+        };
+        let mut path = vec![upper];
+        if rank_of(upper) != rank_of(lower) {
+            for rank in rank_of(upper) + 1..rank_of(lower) {
+                vertices.push(Vertex {
+                    node: None,
+                    rank,
+                    cross: 0.0,
+                    along: 0.0,
+                });
+                path.push(vertices.len() - 1);
+            }
+            path.push(lower);
+            links.extend(path.iter().copied().tuple_windows::<(_, _)>());
+        } else if upper != lower {
+            path.push(lower);
+        }
+        if upper != from {
+            path.reverse();
+        }
+        edge_vertices.push(path);
+    }
+
+    let rank_count = vertices
+        .iter()
+        .map(|v| v.rank + 1)
+        .max()
+        .unwrap_or_default();
+    let mut layers = vec![Vec::new(); rank_count];
+    for (index, vertex) in vertices.iter().enumerate() {
+        layers[vertex.rank].push(index);
+    }
+    let mut ups = vec![Vec::new(); vertices.len()];
+    let mut downs = vec![Vec::new(); vertices.len()];
+    for &(upper, lower) in &links {
+        downs[upper].push(lower);
+        ups[lower].push(upper);
+    }
+    let layers = order(layers, &ups, &downs);
+
+    // positions across the ranks
+    let mut cross = vec![0.0; vertices.len()];
+    let has_loop = |vertex: usize| {
+        graph
+            .edges
+            .iter()
+            .any(|&(from, to, _)| from == to && vertices[vertex].node == Some(from))
+    };
+    let gap = |a: usize, b: usize| {
+        let gap = match vertices[a].node.is_some() && vertices[b].node.is_some() {
+            true => NODE_GAP,
+            false => NODE_GAP / 2.0,
+        };
+        let loop_size = if has_loop(a) { LOOP_SIZE } else { 0.0 };
+        (vertices[a].cross + vertices[b].cross) / 2.0 + gap + loop_size
+    };
+    for layer in &layers {
+        let mut position = 0.0;
+        for (i, &vertex) in layer.iter().enumerate() {
+            if i > 0 {
+                position += gap(layer[i - 1], vertex);
+            }
+            cross[vertex] = position;
+        }
+    }
+    for iteration in 0..8 {
+        let (sweep, neighbors): (Vec<_>, _) = match iteration % 2 {
+            0 => ((1..rank_count).collect(), &ups),
+            _ => ((0..rank_count.saturating_sub(1)).rev().collect(), &downs),
+        };
+        for rank in sweep {
+            let layer = &layers[rank];
+            let desired = layer
+                .iter()
+                .map(|&vertex| match neighbors[vertex].len() {
+                    0 => cross[vertex],
+                    len => neighbors[vertex].iter().map(|&n| cross[n]).sum::<f64>() / len as f64,
+                })
+                .collect_vec();
+            let mut positions = desired.clone();
+            for i in 1..layer.len() {
+                positions[i] = positions[i].max(positions[i - 1] + gap(layer[i - 1], layer[i]));
+            }
+            let shift = desired
+                .iter()
+                .zip(&positions)
+                .map(|(desired, position)| desired - position)
+                .sum::<f64>()
+                / layer.len().max(1) as f64;
+            for (&vertex, position) in layer.iter().zip(positions) {
+                cross[vertex] = position + shift;
+            }
+        }
+    }
+    let min = vertices
+        .iter()
+        .zip(&cross)
+        .map(|(vertex, cross)| cross - vertex.cross / 2.0)
+        .fold(f64::INFINITY, f64::min);
+    for position in &mut cross {
+        *position += MARGIN - min;
+    }
+
+    // positions along the ranks, with room for labels of edges between them if they are horizontal
+    let mut along_offsets = Vec::new();
+    let mut offset = MARGIN;
+    // end of the last rank
+    let mut end = MARGIN;
+    for (rank, layer) in layers.iter().enumerate() {
+        let thickness = layer
+            .iter()
+            .map(|&vertex| vertices[vertex].along)
+            .fold(0.0, f64::max);
+        along_offsets.push(offset + thickness / 2.0);
+        end = offset + thickness;
+        let label_width = graph
+            .edges
+            .iter()
+            .filter(|&&(from, to, _)| {
+                rank_of(from) != rank_of(to) && rank_of(from).min(rank_of(to)) == rank
+            })
+            .filter_map(|(_, _, attrs)| attrs.get("label"))
+            .map(|label| text_size(&label_lines(label, "")).0 + 16.0)
+            .fold(0.0, f64::max);
+        offset += thickness
+            + match rank_dir.horizontal() {
+                true => RANK_GAP.max(label_width),
+                false => RANK_GAP,
+            };
+    }
+    let along_size = end + MARGIN;
+    let has_loops = graph.edges.iter().any(|(from, to, _)| from == to);
+    let cross_size = vertices
+        .iter()
+        .zip(&cross)
+        .map(|(vertex, cross)| cross + vertex.cross / 2.0)
+        .fold(0.0, f64::max)
+        + MARGIN
+        + if has_loops { LOOP_SIZE } else { 0.0 };
+    let point = |vertex: usize| {
+        let along = along_offsets[vertices[vertex].rank];
+        match rank_dir {
+            RankDir::TopBottom => (cross[vertex], along),
+            RankDir::BottomTop => (cross[vertex], along_size - along),
+            RankDir::LeftRight => (along, cross[vertex]),
+            RankDir::RightLeft => (along_size - along, cross[vertex]),
+        }
+    };
+    for (node, layout) in nodes.iter_mut().enumerate() {
+        (layout.x, layout.y) = point(node);
+    }
+    let (width, height) = match rank_dir.horizontal() {
+        true => (along_size, cross_size),
+        false => (cross_size, along_size),
+    };
+
+    let edges = graph
+        .edges
+        .iter()
+        .zip(edge_vertices)
+        .map(|((from, to, attrs), path)| {
+            let mut points = path.iter().map(|&vertex| point(vertex)).collect_vec();
+            let self_loop = from == to;
+            match self_loop {
+                true => {
+                    // on the side of the node across the ranks, away from other edges
+                    let node = &nodes[*from];
+                    points = match rank_dir.horizontal() {
+                        true => {
+                            let (x, y) = (node.x, node.y + node.height / 2.0);
+                            vec![
+                                (x - 6.0, y),
+                                (x - LOOP_SIZE, y + LOOP_SIZE),
+                                (x + LOOP_SIZE, y + LOOP_SIZE),
+                                (x + 6.0, y),
+                            ]
+                        }
+                        false => {
+                            let (x, y) = (node.x + node.width / 2.0, node.y);
+                            vec![
+                                (x, y - 6.0),
+                                (x + LOOP_SIZE, y - LOOP_SIZE),
+                                (x + LOOP_SIZE, y + LOOP_SIZE),
+                                (x, y + 6.0),
+                            ]
+                        }
+                    };
+                }
+                false => {
+                    let last = points.len() - 1;
+                    points[0] = nodes[*from].clip(points[1]);
+                    points[last] = nodes[*to].clip(points[last - 1]);
+                }
+            }
+            let (arrow_head, arrow_tail) = match attrs.get("dir").map(String::as_str) {
+                None => (graph.directed, false),
+                Some("forward") => (true, false),
+                Some("back") => (false, true),
+                Some("both") => (true, true),
+                Some("none") => (false, false),
+                Some(dir) => {
+                    return Err(format!(
+                        "unsupported dir `{dir}`, expected `forward`, `back`, `both` or `none`"
+                    ))
+                }
+            };
+            Ok(EdgeLayout {
+                points,
+                self_loop,
+                label: attrs.get("label").map(|label| label_lines(label, "")),
+                style: Style::new(attrs)?,
+                arrow_head,
+                arrow_tail,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(Layout {
+        nodes,
+        edges,
+        rank_dir,
+        width,
+        height,
+    })
+}
+
+/// Ranks of `count` nodes, such that edges go from lower ranks to higher ones (except the ones
+/// closing cycles, which go the other way).
+fn rank(count: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut outgoing = vec![Vec::new(); count];
+    for &(from, to) in edges {
+        outgoing[from].push(to);
+    }
+    // break cycles by leaving out edges back to nodes being visited by a depth-first search
+    let mut state = vec![0u8; count]; // 0 - not visited, 1 - being visited, 2 - visited
+    let mut acyclic = Vec::new();
+    for start in 0..count {
+        if state[start] != 0 {
+            continue;
+        }
+        let mut stack = vec![(start, 0)];
+        state[start] = 1;
+        while let Some((node, next)) = stack.pop() {
+            match outgoing[node].get(next) {
+                Some(&to) => {
+                    stack.push((node, next + 1));
+                    match state[to] {
+                        0 => {
+                            acyclic.push((node, to));
+                            state[to] = 1;
+                            stack.push((to, 0));
+                        }
+                        2 => acyclic.push((node, to)),
+                        _ => {}
+                    }
+                }
+                None => state[node] = 2,
+            }
+        }
+    }
+    // longest paths from the sources
+    let mut incoming = vec![0; count];
+    for &(_, to) in &acyclic {
+        incoming[to] += 1;
+    }
+    let mut ranks = vec![0; count];
+    let mut ready = (0..count).filter(|&node| incoming[node] == 0).collect_vec();
+    while let Some(node) = ready.pop() {
+        for &(from, to) in &acyclic {
+            if from == node {
+                ranks[to] = ranks[to].max(ranks[node] + 1);
+                incoming[to] -= 1;
+                if incoming[to] == 0 {
+                    ready.push(to);
+                }
+            }
+        }
+    }
+    ranks
+}
+
+/// Order vertices of every layer to reduce crossings of edges, by sorting them by the average
+/// position of their neighbors in the adjacent layer.
+fn order(mut layers: Vec<Vec<usize>>, ups: &[Vec<usize>], downs: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let positions = |layers: &[Vec<usize>]| {
+        let mut positions = vec![0; ups.len()];
+        for layer in layers {
+            for (position, &vertex) in layer.iter().enumerate() {
+                positions[vertex] = position;
+            }
+        }
+        positions
+    };
+    let crossings = |layers: &[Vec<usize>]| {
+        let positions = positions(layers);
+        layers
+            .iter()
+            .map(|layer| {
+                let links = layer
+                    .iter()
+                    .flat_map(|&upper| downs[upper].iter().map(move |&lower| (upper, lower)))
+                    .map(|(upper, lower)| (positions[upper], positions[lower]))
+                    .collect_vec();
+                links
+                    .iter()
+                    .tuple_combinations()
+                    .filter(|((a1, b1), (a2, b2))| (a1 < a2 && b1 > b2) || (a1 > a2 && b1 < b2))
+                    .count()
+            })
+            .sum::<usize>()
+    };
+    let mut best = (crossings(&layers), layers.clone());
+    for iteration in 0..12 {
+        let (sweep, neighbors): (Vec<_>, _) = match iteration % 2 {
+            0 => ((1..layers.len()).collect(), ups),
+            _ => ((0..layers.len().saturating_sub(1)).rev().collect(), downs),
+        };
+        for rank in sweep {
+            let positions = positions(&layers);
+            let barycenter = |vertex: usize| match neighbors[vertex].len() {
+                0 => positions[vertex] as f64,
+                len => {
+                    neighbors[vertex]
+                        .iter()
+                        .map(|&n| positions[n])
+                        .sum::<usize>() as f64
+                        / len as f64
+                }
+            };
+            layers[rank].sort_by(|&a, &b| barycenter(a).total_cmp(&barycenter(b)));
+        }
+        let crossings = crossings(&layers);
+        if crossings < best.0 {
+            best = (crossings, layers.clone());
+        }
+    }
+    best.1
+}
+
+//
+// rendering
+//
+
+fn render(graph: &Graph, layout: &Layout) -> String {
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="{FONT_SIZE}" text-anchor="middle" role="img">"#,
+        w = layout.width.ceil(),
+        h = layout.height.ceil(),
+    );
+    if let Some(label) = graph.attrs.get("label") {
+        let _ = write!(
+            svg,
+            "<title>{}</title>",
+            escape_xml(&label_lines(label, "").join(" "))
+        );
+    }
+    for edge in &layout.edges {
+        if edge.style.invisible {
+            continue;
+        }
+        svg.push_str(r#"<g class="edge">"#);
+        let stroke = edge.style.stroke();
+        let (start, end) = (edge.points[0], edge.points[edge.points.len() - 1]);
+        // points the arrows come from
+        let (before_end, after_start) = (edge.points[edge.points.len() - 2], edge.points[1]);
+        if edge.self_loop {
+            let (from, (x1, y1), (x2, y2), to) = (
+                edge.points[0],
+                edge.points[1],
+                edge.points[2],
+                edge.points[3],
+            );
+            let _ = write!(
+                svg,
+                r#"<path d="M {:.1} {:.1} C {x1:.1} {y1:.1} {x2:.1} {y2:.1} {:.1} {:.1}" fill="none"{stroke}/>"#,
+                from.0, from.1, to.0, to.1
+            );
+        } else {
+            let _ = write!(
+                svg,
+                r#"<polyline points="{}" fill="none"{stroke}/>"#,
+                edge.points
+                    .iter()
+                    .map(|(x, y)| format!("{x:.1},{y:.1}"))
+                    .join(" ")
+            );
+        }
+        if edge.arrow_head {
+            arrow(&mut svg, before_end, end, &edge.style);
+        }
+        if edge.arrow_tail {
+            arrow(&mut svg, after_start, start, &edge.style);
+        }
+        if let (Some(label), true) = (&edge.label, edge.self_loop) {
+            let ((x1, y1), (x2, y2)) = (edge.points[0], edge.points[2]);
+            match layout.rank_dir.horizontal() {
+                true => {
+                    let (_, height) = text_size(label);
+                    text(
+                        &mut svg,
+                        label,
+                        (x1 + 6.0, y2 + height / 2.0),
+                        "middle",
+                        &edge.style,
+                    )
+                }
+                false => text(&mut svg, label, (x2 + 4.0, y1 + 6.0), "start", &edge.style),
+            }
+        } else if let Some(label) = &edge.label {
+            let middle = edge.points.len() / 2;
+            let (x, y) = match edge.points.len() % 2 {
+                1 => edge.points[middle],
+                _ => {
+                    let ((x1, y1), (x2, y2)) = (edge.points[middle - 1], edge.points[middle]);
+                    ((x1 + x2) / 2.0, (y1 + y2) / 2.0)
+                }
+            };
+            match layout.rank_dir.horizontal() {
+                true => {
+                    let (_, height) = text_size(label);
+                    text(
+                        &mut svg,
+                        label,
+                        (x, y - height / 2.0 - 2.0),
+                        "middle",
+                        &edge.style,
+                    )
+                }
+                false => text(&mut svg, label, (x + 6.0, y), "start", &edge.style),
+            }
+        }
+        svg.push_str("</g>");
+    }
+    for node in &layout.nodes {
+        if node.style.invisible {
+            continue;
+        }
+        svg.push_str(r#"<g class="node">"#);
+        let style = &node.style;
+        let fill = match style.filled {
+            true => match style.fill_color.as_ref().or(style.color.as_ref()) {
+                Some(color) => format!(r#" fill="{color}""#),
+                None => r#" fill="currentColor" fill-opacity="0.15""#.to_owned(),
+            },
+            false => r#" fill="none""#.to_owned(),
+        };
+        let stroke = style.stroke();
+        let (x, y, w, h) = (node.x, node.y, node.width, node.height);
+        let _ = match node.shape {
+            Shape::Box => write!(
+                svg,
+                r#"<rect x="{:.1}" y="{:.1}" width="{w:.1}" height="{h:.1}" rx="{}"{fill}{stroke}/>"#,
+                x - w / 2.0,
+                y - h / 2.0,
+                if style.rounded { 6 } else { 0 },
+            ),
+            Shape::Ellipse | Shape::Circle => write!(
+                svg,
+                r#"<ellipse cx="{x:.1}" cy="{y:.1}" rx="{:.1}" ry="{:.1}"{fill}{stroke}/>"#,
+                w / 2.0,
+                h / 2.0,
+            ),
+            Shape::Diamond => write!(
+                svg,
+                r#"<polygon points="{:.1},{y:.1} {x:.1},{:.1} {:.1},{y:.1} {x:.1},{:.1}"{fill}{stroke}/>"#,
+                x - w / 2.0,
+                y - h / 2.0,
+                x + w / 2.0,
+                y + h / 2.0,
+            ),
+            Shape::Plaintext if style.filled => write!(
+                svg,
+                r#"<rect x="{:.1}" y="{:.1}" width="{w:.1}" height="{h:.1}"{fill}/>"#,
+                x - w / 2.0,
+                y - h / 2.0,
+            ),
+            Shape::Plaintext => Ok(()),
+        };
+        text(&mut svg, &node.lines, (x, y), "middle", style);
+        svg.push_str("</g>");
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Arrow head pointing to `tip` from `from`.
+fn arrow(svg: &mut String, from: (f64, f64), tip: (f64, f64), style: &Style) {
+    let (dx, dy) = (tip.0 - from.0, tip.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        return;
+    }
+    let (ux, uy) = (dx / length, dy / length);
+    let (bx, by) = (tip.0 - ux * ARROW_LENGTH, tip.1 - uy * ARROW_LENGTH);
+    let color = style.color.as_deref().unwrap_or("currentColor");
+    let _ = write!(
+        svg,
+        r#"<polygon points="{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" fill="{color}"/>"#,
+        tip.0,
+        tip.1,
+        bx - uy * ARROW_WIDTH,
+        by + ux * ARROW_WIDTH,
+        bx + uy * ARROW_WIDTH,
+        by - ux * ARROW_WIDTH,
+    );
+}
+
+/// Lines of text centered vertically at `(x, y)`.
+fn text(svg: &mut String, lines: &[String], (x, y): (f64, f64), anchor: &str, style: &Style) {
+    let fill = style.font_color.as_deref().unwrap_or("currentColor");
+    let top = y - (lines.len() - 1) as f64 * LINE_HEIGHT / 2.0;
+    for (i, line) in lines.iter().enumerate() {
+        let _ = write!(
+            svg,
+            r#"<text x="{x:.1}" y="{:.1}" text-anchor="{anchor}" dominant-baseline="central" fill="{fill}">{}</text>"#,
+            top + i as f64 * LINE_HEIGHT,
+            escape_xml(line)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Graph {
+        Parser::new(source).unwrap().graph().unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        dot_to_svg(source).unwrap_err()
+    }
+
+    fn node_ids(graph: &Graph) -> Vec<&str> {
+        graph.nodes.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    fn edge_ends(graph: &Graph) -> Vec<(usize, usize)> {
+        graph
+            .edges
+            .iter()
+            .map(|&(from, to, _)| (from, to))
+            .collect()
+    }
+
+    #[test]
+    fn statements() {
+        let graph = parse(
+            r#"
+            // comment
+            strict digraph G {
+                rankdir = LR
+                node [shape=box]
+                a [label="A\nB"]; "b c" -> d:port -> a /* comment */
+                edge [style=dashed]
+                a -> { e f } [color=red]
+            }
+            "#,
+        );
+        assert!(graph.directed);
+        assert_eq!(graph.attrs["rankdir"], "LR");
+        assert_eq!(node_ids(&graph), ["a", "b c", "d", "e", "f"]);
+        assert_eq!(graph.nodes[0].1["label"], "A\\nB");
+        assert!(graph.nodes.iter().all(|(_, attrs)| attrs["shape"] == "box"));
+        assert_eq!(edge_ends(&graph), [(1, 2), (2, 0), (0, 3), (0, 4)]);
+        assert!(!graph.edges[0].2.contains_key("style"));
+        assert_eq!(graph.edges[2].2["style"], "dashed");
+        assert_eq!(graph.edges[3].2["color"], "red");
+    }
+
+    #[test]
+    fn undirected() {
+        let graph = parse("graph { a -- b -- c }");
+        assert!(!graph.directed);
+        assert_eq!(edge_ends(&graph), [(0, 1), (1, 2)]);
+        let svg = dot_to_svg("graph { a -- b }").unwrap();
+        assert!(!svg.contains("<polygon"));
+    }
+
+    #[test]
+    fn subgraph_defaults() {
+        let graph = parse("digraph { subgraph s { node [shape=box]; a } b }");
+        assert_eq!(graph.nodes[0].1["shape"], "box");
+        assert!(!graph.nodes[1].1.contains_key("shape"));
+    }
+
+    #[test]
+    fn ranks() {
+        assert_eq!(rank(4, &[(0, 1), (1, 2), (0, 2), (3, 2)]), [0, 1, 2, 0]);
+        assert_eq!(rank(0, &[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn cycles() {
+        assert_eq!(rank(3, &[(0, 1), (1, 2), (2, 0)]), [0, 1, 2]);
+        assert_eq!(rank(2, &[(0, 1), (1, 0)]), [0, 1]);
+        let graph = parse("digraph { a -> b -> c -> a }");
+        let laid_out = layout(&graph).unwrap();
+        let ys = laid_out.nodes.iter().map(|node| node.y).collect_vec();
+        assert!(ys[0] < ys[1] && ys[1] < ys[2]);
+        // the edge closing the cycle goes back up
+        let back = &laid_out.edges[2].points;
+        assert!(back.first().unwrap().1 > back.last().unwrap().1);
+    }
+
+    #[test]
+    fn self_loops() {
+        let graph = parse("digraph { a -> a; a -> b }");
+        let laid_out = layout(&graph).unwrap();
+        assert!(laid_out.edges[0].self_loop);
+        assert_eq!(laid_out.edges[0].points.len(), 4);
+        assert!(!laid_out.edges[1].self_loop);
+        assert!(laid_out.nodes[0].y < laid_out.nodes[1].y);
+        // the loop fits in the drawing
+        let right = laid_out.edges[0]
+            .points
+            .iter()
+            .map(|p| p.0)
+            .fold(0.0, f64::max);
+        assert!(right < laid_out.width);
+    }
+
+    #[test]
+    fn same_rank() {
+        let graph = parse("digraph { a -> b -> c; a -> d; { rank = same; b; d } }");
+        assert_eq!(graph.same_ranks, [vec![1, 3]]);
+        let laid_out = layout(&graph).unwrap();
+        let ys = laid_out.nodes.iter().map(|node| node.y).collect_vec();
+        assert_eq!(ys[1], ys[3]);
+        assert!(ys[0] < ys[1] && ys[1] < ys[2]);
+
+        let graph = parse("digraph { a -> b; subgraph { graph [rank=same] a b } }");
+        let laid_out = layout(&graph).unwrap();
+        assert_eq!(laid_out.nodes[0].y, laid_out.nodes[1].y);
+        assert_ne!(laid_out.nodes[0].x, laid_out.nodes[1].x);
+    }
+
+    #[test]
+    fn crossings() {
+        // 0 -> 3 and 1 -> 2 cross unless one of the layers is reversed
+        let ups = [vec![], vec![], vec![1], vec![0]];
+        let downs = [vec![3], vec![2], vec![], vec![]];
+        let layers = order(vec![vec![0, 1], vec![2, 3]], &ups, &downs);
+        let position = |vertex| {
+            layers
+                .iter()
+                .find_map(|layer| layer.iter().position(|&v| v == vertex))
+        };
+        assert_eq!(position(0) < position(1), position(3) < position(2));
+    }
+
+    #[test]
+    fn empty() {
+        let svg = dot_to_svg("digraph {}").unwrap();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>"));
+        assert!(!svg.contains("NaN") && !svg.contains("inf"));
+        assert!(dot_to_svg("graph G { ; }").is_ok());
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(error(""), "expected `graph` or `digraph`, found the end");
+        assert_eq!(
+            error("flowchart {}"),
+            "expected `graph` or `digraph`, found `flowchart`"
+        );
+        assert_eq!(error("digraph { a -> b"), "expected `}`, found the end");
+        assert_eq!(error("digraph {} x"), "expected the end, found `x`");
+        assert_eq!(
+            error("digraph { a -- b }"),
+            "expected `->` in this graph, found `--`"
+        );
+        assert_eq!(
+            error("digraph { a -> }"),
+            "expected an identifier, found `}`"
+        );
+        assert_eq!(error(r#"digraph { "a }"#), "unterminated string");
+        assert_eq!(error("digraph { /* a }"), "unterminated comment");
+        assert_eq!(
+            error("digraph { a [label=<b>] }"),
+            "HTML labels are not supported"
+        );
+        assert_eq!(
+            error("digraph { rank = min }"),
+            "unsupported rank `min`, expected `same`"
+        );
+        assert!(error("digraph { a [shape=star] }").starts_with("unsupported shape `star`"));
+        assert!(error("digraph { rankdir = XY }").starts_with("unsupported rankdir `XY`"));
+        assert!(error("digraph { a -> b [dir=up] }").starts_with("unsupported dir `up`"));
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+use implicit_clone::sync::IString;
+use implicit_clone::ImplicitClone;
+use itertools::Itertools;
+
+use crate::sync::path::IPath;
+
+/// Machine-readable kind of an [`Error`]. See [`ErrorKind::code`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ErrorKind {
+    /// File or directory could not be read.
+    Io,
+    /// File (or front matter) is not valid TOML (or YAML).
+    Syntax,
+    /// Required key is missing.
+    MissingKey,
+    /// Value is of wrong type.
+    InvalidValue,
+    /// Language is not defined in the languages file.
+    UnknownLanguage,
+    /// Tag is not defined in the tags directory.
+    UnknownTag,
+    /// Page has neither a meta file nor front matter.
+    MissingMeta,
+    /// Page has multiple meta files.
+    DuplicateMeta,
+    /// Meta key is defined both in the meta file and in front matter, or in multiple front matters.
+    ConflictingMeta,
+    /// Page has multiple files for the same language.
+    DuplicatePage,
+    /// Template failed to compile or render.
+    Template,
+    /// I18n key is not defined in the i18n file.
+    MissingI18n,
+    /// Link points to something that does not exist.
+    BrokenLink,
+    /// External link is not known to be valid.
+    UncheckedLink,
+    /// Page file was last changed before the page file in the default language.
+    OutdatedTranslation,
+    /// Math formula is not in the supported subset of LaTeX.
+    Math,
+    /// Diagram is not in the supported subset of the DOT language.
+    Diagram,
+    /// Info string of a code block is not valid.
+    CodeBlock,
+    /// Base URL is not absolute, as required by some outputs.
+    BaseUrl,
+}
+
+impl ErrorKind {
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorKind::Io => "io",
+            ErrorKind::Syntax => "syntax",
+            ErrorKind::MissingKey => "missing-key",
+            ErrorKind::InvalidValue => "invalid-value",
+            ErrorKind::UnknownLanguage => "unknown-language",
+            ErrorKind::UnknownTag => "unknown-tag",
+            ErrorKind::MissingMeta => "missing-meta",
+            ErrorKind::DuplicateMeta => "duplicate-meta",
+            ErrorKind::ConflictingMeta => "conflicting-meta",
+            ErrorKind::DuplicatePage => "duplicate-page",
+            ErrorKind::Template => "template",
+            ErrorKind::MissingI18n => "missing-i18n",
+            ErrorKind::BrokenLink => "broken-link",
+            ErrorKind::UncheckedLink => "unchecked-link",
+            ErrorKind::OutdatedTranslation => "outdated-translation",
+            ErrorKind::Math => "math",
+            ErrorKind::Diagram => "diagram",
+            ErrorKind::CodeBlock => "code-block",
+            ErrorKind::BaseUrl => "base-url",
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// 1-based line and column (in characters) of a position in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// Find the line and the column of byte `offset` in the `source`.
+    pub fn from_offset(source: &str, offset: usize) -> Location {
+        let before = &source[..offset.min(source.len())];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl ImplicitClone for Location {}
+
+/// A problem with the source directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// File (or directory) the problem was found in.
+    pub path: Option<IPath>,
+    pub location: Option<Location>,
+    pub message: IString,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<IString>) -> Error {
+        Error {
+            kind,
+            path: None,
+            location: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn with_path(self, path: IPath) -> Error {
+        Error {
+            path: Some(path),
+            ..self
+        }
+    }
+
+    pub fn with_location(self, location: Location) -> Error {
+        Error {
+            location: Some(location),
+            ..self
+        }
+    }
+}
+
+impl ImplicitClone for Error {}
+
+impl Error {
+    /// Formats as `path:line:column: severity[kind]: message`, omitting the unknown parts.
+    fn fmt_as(&self, f: &mut Formatter<'_>, severity: &str) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+            if let Some(Location { line, column }) = self.location {
+                write!(f, "{line}:{column}:")?;
+            }
+            f.write_str(" ")?;
+        }
+        write!(f, "{severity}[{}]: {}", self.kind, self.message)
+    }
+}
+
+impl Display for Error {
+    /// Formats as `path:line:column: error[kind]: message`, omitting the unknown parts.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.fmt_as(f, "error")
+    }
+}
+
+/// [`Error`] displayed as a warning.
+struct Warning<'a>(&'a Error);
+
+impl Display for Warning<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.0.fmt_as(f, "warning")
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// All errors collected during a run, so that they can be reported together. Warnings are reported
+/// as well, but unlike errors they do not fail the run.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    errors: Vec<Error>,
+    warnings: Vec<Error>,
+}
+
+impl Diagnostics {
+    /// Add an error, unless the same one was already added (e.g. by the same template rendered
+    /// for multiple pages).
+    pub fn push(&mut self, error: Error) {
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+
+    /// Add a warning, unless the same one was already added.
+    pub fn warn(&mut self, warning: Error) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Whether there are no errors (warnings are not counted).
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Error> + '_ {
+        self.errors.iter()
+    }
+
+    /// Print all warnings and errors to stderr, followed by a summary line of each.
+    pub fn report(&self) {
+        for warning in &self.warnings {
+            eprintln!("{}", Warning(warning));
+        }
+        for error in &self.errors {
+            eprintln!("{error}");
+        }
+        for (errors, severity) in [(&self.warnings, "warning"), (&self.errors, "error")] {
+            if !errors.is_empty() {
+                eprintln!(
+                    "{} {severity}(s): {}",
+                    errors.len(),
+                    errors
+                        .iter()
+                        .counts_by(|error| error.kind)
+                        .into_iter()
+                        .sorted()
+                        .map(|(kind, count)| format!("{count} {kind}"))
+                        .join(", ")
+                );
+            }
+        }
+    }
+}
+
+impl Extend<Error> for Diagnostics {
+    fn extend<T: IntoIterator<Item = Error>>(&mut self, iter: T) {
+        for error in iter {
+            self.push(error);
+        }
+    }
+}
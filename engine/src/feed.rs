@@ -0,0 +1,95 @@
+use std::fmt::Write;
+
+use implicit_clone::sync::IString;
+use itertools::Itertools;
+
+use crate::date::Date;
+use crate::utils::escape_xml;
+
+/// Atom feed of pages.
+#[derive(Debug, Clone, Hash)]
+pub struct Feed {
+    /// URL of the feed itself.
+    pub url: IString,
+    /// URL of the page the feed is of.
+    pub link: IString,
+    /// Locale of the feed (e.g. `en-US`).
+    pub lang: IString,
+    pub title: IString,
+    pub description: IString,
+    pub entries: Vec<FeedEntry>,
+}
+
+#[derive(Debug, Clone, Hash)]
+pub struct FeedEntry {
+    pub url: IString,
+    pub title: IString,
+    pub description: IString,
+    /// Ids and titles of tags.
+    pub categories: Vec<(IString, IString)>,
+    pub published: Option<Date>,
+    pub updated: Date,
+}
+
+impl Feed {
+    /// Render the feed as Atom XML. Entries are sorted from the most recently updated.
+    pub fn to_atom(&self) -> IString {
+        let mut xml = String::new();
+        let updated = self
+            .entries
+            .iter()
+            .map(|entry| entry.updated)
+            .max()
+            .unwrap_or_default();
+        let _ = write!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"{}\">\n\
+             <id>{}</id>\n\
+             <title>{}</title>\n\
+             <subtitle>{}</subtitle>\n\
+             <link rel=\"self\" href=\"{}\"/>\n\
+             <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n\
+             <updated>{updated}</updated>\n",
+            escape_xml(&self.lang),
+            escape_xml(&self.link),
+            escape_xml(&self.title),
+            escape_xml(&self.description),
+            escape_xml(&self.url),
+            escape_xml(&self.link),
+        );
+        for entry in self
+            .entries
+            .iter()
+            .sorted_by(|a, b| b.updated.cmp(&a.updated).then_with(|| a.url.cmp(&b.url)))
+        {
+            let _ = write!(
+                xml,
+                "<entry>\n\
+                 <id>{}</id>\n\
+                 <title>{}</title>\n\
+                 <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n\
+                 <summary>{}</summary>\n",
+                escape_xml(&entry.url),
+                escape_xml(&entry.title),
+                escape_xml(&entry.url),
+                escape_xml(&entry.description),
+            );
+            if let Some(published) = entry.published {
+                let _ = writeln!(xml, "<published>{published}</published>");
+            }
+            let _ = writeln!(xml, "<updated>{}</updated>", entry.updated);
+            for (id, title) in &entry.categories {
+                let _ = writeln!(
+                    xml,
+                    "<category term=\"{}\" label=\"{}\"/>",
+                    escape_xml(id),
+                    escape_xml(title)
+                );
+            }
+            xml.push_str("</entry>\n");
+        }
+        xml.push_str("</feed>\n");
+        xml.into()
+    }
+}
@@ -0,0 +1,253 @@
+use std::fs;
+use std::ops::Range;
+
+use toml::value::Datetime;
+use toml::Spanned;
+use yaml_rust2::Yaml;
+use yaml_rust2::YamlLoader;
+
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::sync::path::IPath;
+use crate::utils::SpannedTable;
+use crate::utils::TomlSource;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Delimited by `+++` lines.
+    Toml,
+    /// Delimited by `---` lines.
+    Yaml,
+}
+
+/// Front matter at the start of a page file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrontMatter {
+    pub format: Format,
+    /// Byte range of the contents between the delimiter lines.
+    pub body: Range<usize>,
+    /// Byte length of the front matter, including the delimiter lines.
+    pub len: usize,
+}
+
+/// Find the front matter at the start of `source`, if there is one.
+pub fn find_front_matter(source: &str) -> Option<FrontMatter> {
+    let mut lines = source.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        Some((start, line))
+    });
+    let (_, first) = lines.next()?;
+    let (format, delimiter) = match first.trim_end() {
+        "+++" => (Format::Toml, "+++"),
+        "---" => (Format::Yaml, "---"),
+        _ => return None,
+    };
+    let (end, last) = lines.find(|(_, line)| line.trim_end() == delimiter)?;
+    // `---` lines are also thematic breaks, so YAML front matter has to start with a key
+    if format == Format::Yaml && !starts_with_key(&source[first.len()..end]) {
+        return None;
+    }
+    Some(FrontMatter {
+        format,
+        body: first.len()..end,
+        len: end + last.len(),
+    })
+}
+
+/// Whether the first line of the YAML `body` that is not empty or a comment is a top-level `key:`.
+fn starts_with_key(body: &str) -> bool {
+    body.lines()
+        .map(str::trim_end)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .and_then(|line| line.split_once(':'))
+        .is_some_and(|(key, value)| {
+            !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+                && (value.is_empty() || value.starts_with(' '))
+        })
+}
+
+/// Replace the front matter of `source` with empty lines, so that locations in the rest of the
+/// page file stay the same.
+pub fn strip_front_matter(source: &str) -> String {
+    match find_front_matter(source) {
+        Some(front_matter) => {
+            let lines = source[..front_matter.len].matches('\n').count();
+            "\n".repeat(lines) + &source[front_matter.len..]
+        }
+        None => source.to_owned(),
+    }
+}
+
+/// Read and parse the front matter of a page file into a table like the one of a meta file.
+/// Locations of problems are in the page file.
+pub fn read_front_matter(path: IPath) -> Result<Option<(TomlSource, SpannedTable)>, Error> {
+    let source = fs::read_to_string(&path)
+        .map_err(|err| Error::new(ErrorKind::Io, err.to_string()).with_path(path.clone()))?;
+    let Some(front_matter) = find_front_matter(&source) else {
+        return Ok(None);
+    };
+    let file = TomlSource {
+        path,
+        source: source.into(),
+    };
+    let body = &file.source[front_matter.body.clone()];
+    let table = match front_matter.format {
+        Format::Toml => {
+            // keep the offsets of the page file, so that spans point into it
+            let padded = " ".repeat(front_matter.body.start) + body;
+            toml::from_str(&padded)
+                .map_err(|err| file.error(ErrorKind::Syntax, err.span(), err.message()))?
+        }
+        Format::Yaml => yaml_table(&file, body, front_matter.body.start)?,
+    };
+    Ok(Some((file, table)))
+}
+
+/// Convert the YAML `body` of the front matter starting at byte `offset` of the `file`. Values are
+/// located at their top-level keys.
+fn yaml_table(file: &TomlSource, body: &str, offset: usize) -> Result<SpannedTable, Error> {
+    let documents = YamlLoader::load_from_str(body).map_err(|err| {
+        let index = body
+            .char_indices()
+            .nth(err.marker().index())
+            .map_or(body.len(), |(index, _)| index);
+        file.error(
+            ErrorKind::Syntax,
+            Some(offset + index..offset + index),
+            err.info(),
+        )
+    })?;
+    let entries = match documents.into_iter().next() {
+        None | Some(Yaml::Null) => return Ok(SpannedTable::new()),
+        Some(Yaml::Hash(entries)) => entries,
+        Some(_) => {
+            return Err(file.error(
+                ErrorKind::InvalidValue,
+                Some(offset..offset),
+                "front matter should be a mapping of keys to values",
+            ))
+        }
+    };
+    entries
+        .into_iter()
+        .map(|(key, value)| {
+            let Yaml::String(key) = key else {
+                return Err(file.error(
+                    ErrorKind::InvalidValue,
+                    Some(offset..offset),
+                    "keys of the front matter should be strings",
+                ));
+            };
+            let start = body
+                .split_inclusive('\n')
+                .scan(offset, |line_start, line| {
+                    let start = *line_start;
+                    *line_start += line.len();
+                    Some((start, line))
+                })
+                .find_map(|(start, line)| {
+                    let value = line.strip_prefix(key.as_str())?.strip_prefix(':')?;
+                    // nested values start on the following lines
+                    Some(match value.trim().is_empty() {
+                        true => start,
+                        false => start + line.len() - value.trim_start().len(),
+                    })
+                })
+                .unwrap_or(offset);
+            let value = match (key.as_str(), toml_value(value)) {
+                // YAML has no dates, so they are parsed from the strings of the date keys only,
+                // other strings (e.g. a title like `2025-01-31`) stay strings
+                ("created" | "updated", Ok(toml::Value::String(string))) => Ok(string
+                    .parse::<Datetime>()
+                    .map_or(toml::Value::String(string), toml::Value::Datetime)),
+                (_, value) => value,
+            }
+            .map_err(|message| {
+                file.error(
+                    ErrorKind::InvalidValue,
+                    Some(start..start),
+                    format!("invalid `{key}`: {message}"),
+                )
+            })?;
+            Ok((key, Spanned::new(start..start, value)))
+        })
+        .collect()
+}
+
+/// Convert a YAML value to the TOML one.
+fn toml_value(value: Yaml) -> Result<toml::Value, String> {
+    Ok(match value {
+        Yaml::Real(real) => toml::Value::Float(real.parse().map_err(|_| "invalid number")?),
+        Yaml::Integer(integer) => toml::Value::Integer(integer),
+        Yaml::String(string) => toml::Value::String(string),
+        Yaml::Boolean(boolean) => toml::Value::Boolean(boolean),
+        Yaml::Array(values) => toml::Value::Array(
+            values
+                .into_iter()
+                .map(toml_value)
+                .collect::<Result<_, _>>()?,
+        ),
+        Yaml::Hash(entries) => toml::Value::Table(
+            entries
+                .into_iter()
+                .map(|(key, value)| match key {
+                    Yaml::String(key) => Ok((key, toml_value(value)?)),
+                    _ => Err("keys should be strings".to_owned()),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        Yaml::Null => return Err("empty values are not supported".to_owned()),
+        Yaml::Alias(_) | Yaml::BadValue => return Err("aliases are not supported".to_owned()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml() {
+        let source = "+++\ntitle = \"A\"\n+++\n# A\n";
+        assert_eq!(
+            find_front_matter(source),
+            Some(FrontMatter {
+                format: Format::Toml,
+                body: 4..16,
+                len: 20,
+            })
+        );
+    }
+
+    #[test]
+    fn yaml() {
+        let source = "---\n# comment\n\ntitle: A\ntags:\n  - b\n---\n# A\n";
+        assert_eq!(
+            find_front_matter(source),
+            Some(FrontMatter {
+                format: Format::Yaml,
+                body: 4..36,
+                len: 40,
+            })
+        );
+    }
+
+    #[test]
+    fn thematic_breaks() {
+        assert_eq!(find_front_matter("---\n\nText\n\n---\n\nText\n"), None);
+        assert_eq!(find_front_matter("---\n- Item\n---\n"), None);
+        assert_eq!(find_front_matter("---\n---\n"), None);
+    }
+
+    #[test]
+    fn strip() {
+        assert_eq!(
+            strip_front_matter("---\ntitle: A\n---\n# A\n"),
+            "\n\n\n# A\n"
+        );
+        assert_eq!(strip_front_matter("---\n\n# A\n---\n"), "---\n\n# A\n---\n");
+    }
+}
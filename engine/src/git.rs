@@ -0,0 +1,53 @@
+use std::process::Command;
+
+use implicit_clone::sync::IMap;
+use implicit_clone::ImplicitClone;
+
+use crate::date::Date;
+use crate::sync::path::IPath;
+
+/// Dates of the last commits that changed the files in a directory, read from its history in a
+/// single `git log`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitDates {
+    dates: IMap<IPath, Date>,
+}
+
+impl ImplicitClone for GitDates {}
+
+impl GitDates {
+    /// Read the history of the directory at `dir_path`. If git is not available or the directory is
+    /// not in a repository, no file has a date.
+    pub fn read(dir_path: IPath) -> GitDates {
+        let output = Command::new("git")
+            .current_dir(&dir_path)
+            .args(["-c", "core.quotePath=false"])
+            .args(["log", "--name-only", "--relative"])
+            // dates are prefixed with a NUL character to be told apart from file names
+            .args(["--format=%x00%ct", "--", "."])
+            .output()
+            .ok()
+            .filter(|output| output.status.success());
+        let Some(output) = output else {
+            return GitDates::default();
+        };
+        let mut dates = Vec::new();
+        let mut date = None;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            match line.strip_prefix('\0') {
+                Some(timestamp) => date = timestamp.parse().ok().map(Date),
+                None if line.is_empty() => {}
+                None => dates.extend(date.map(|date| (dir_path.join(line), date))),
+            }
+        }
+        // commits are listed from the most recent one, which is the one kept for every file
+        GitDates {
+            dates: dates.into_iter().rev().collect(),
+        }
+    }
+
+    /// Date of the last commit that changed the file at `path`, unless it is not committed.
+    pub fn last_commit_date(&self, path: IPath) -> Option<Date> {
+        self.dates.get(&path)
+    }
+}
@@ -1,96 +1,195 @@
-use std::fs;
-use std::ops::Index;
-
-use implicit_clone::sync::IMap;
-use implicit_clone::sync::IString;
-use implicit_clone::ImplicitClone;
-
-use crate::language::Language;
-use crate::language::LanguageStore;
-use crate::sync::path::IPath;
-use crate::utils::GetRef;
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct I18n {
-    pub default_display: IString,
-    pub displays: IMap<Language, IString>,
-}
-
-impl ImplicitClone for I18n {}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct I18nStore {
-    pub i18ns: IMap<IString, I18n>,
-}
-
-impl ImplicitClone for I18nStore {}
-
-impl I18nStore {
-    pub fn display(&self, key: IString, lang: Language) -> Option<&IString> {
-        self.get(key, lang)
-    }
-
-    pub fn iter_ids(&self) -> impl Iterator<Item = &IString> + '_ {
-        self.i18ns.keys()
-    }
-
-    fn get(&self, key: IString, lang: Language) -> Option<&IString> {
-        self.i18ns.get_ref(&key).map(|i18n| {
-            i18n.displays
-                .get_ref(&lang)
-                .unwrap_or(&i18n.default_display)
-        })
-    }
-}
-
-impl Index<(IString, Language)> for I18nStore {
-    type Output = IString;
-
-    fn index(&self, (key, lang): (IString, Language)) -> &IString {
-        self.get(key.clone(), lang)
-            .unwrap_or_else(|| panic!("requested key {key} not found"))
-    }
-}
-
-impl Index<(Language, IString)> for I18nStore {
-    type Output = IString;
-
-    fn index(&self, (lang, key): (Language, IString)) -> &IString {
-        &self[(key, lang)]
-    }
-}
-
-pub fn process_i18n(i18n_file_path: IPath, languages: LanguageStore) -> I18nStore {
-    I18nStore {
-        i18ns: toml::from_str::<toml::Table>(&fs::read_to_string(i18n_file_path).unwrap())
-            .unwrap()
-            .into_iter()
-            .map(|(i18n_id, value)| {
-                let i18n_id: IString = i18n_id.into();
-                let mut table = value.try_into::<toml::Table>().unwrap();
-                (
-                    i18n_id.clone(),
-                    I18n {
-                        default_display: table
-                            .remove("default")
-                            .unwrap_or_else(|| panic!("missing `default` in i18n {i18n_id}"))
-                            .try_into()
-                            .unwrap(),
-                        displays: table
-                            .into_iter()
-                            .map(|(lang_id, display)| {
-                                let lang_id: IString = lang_id.into();
-                                (
-                                    languages.get(lang_id.clone()).ok().unwrap_or_else(|| {
-                                        panic!("invalid language {lang_id} in i18n {i18n_id}")
-                                    }),
-                                    display.try_into().unwrap(),
-                                )
-                            })
-                            .collect(),
-                    },
-                )
-            })
-            .collect(),
-    }
-}
+use std::collections::BTreeMap;
+use std::ops::Index;
+use std::ops::Range;
+
+use implicit_clone::sync::IMap;
+use implicit_clone::sync::IString;
+use implicit_clone::ImplicitClone;
+use itertools::Itertools;
+use toml::Spanned;
+
+use crate::error::Diagnostics;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::language::Language;
+use crate::language::LanguageStore;
+use crate::message::Arg;
+use crate::message::Message;
+use crate::sync::path::IPath;
+use crate::utils::GetRef;
+use crate::utils::SpannedTable;
+use crate::utils::TomlSource;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct I18n {
+    pub default_display: IString,
+    pub displays: IMap<Language, IString>,
+}
+
+impl ImplicitClone for I18n {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct I18nStore {
+    pub i18ns: IMap<IString, I18n>,
+    /// Language of the default displays.
+    pub default_lang: Language,
+}
+
+impl ImplicitClone for I18nStore {}
+
+impl I18nStore {
+    pub fn display(&self, key: IString, lang: Language) -> Option<&IString> {
+        self.get(key, lang)
+    }
+
+    /// Display of the i18n `key` in `lang`, with its placeholders replaced by `args`. Plural variants
+    /// are chosen by the rules of the language the display is in, which is not `lang` if it falls
+    /// back to another one.
+    pub fn format(
+        &self,
+        key: IString,
+        lang: Language,
+        args: &[(&str, Arg)],
+    ) -> Result<String, String> {
+        let (display_lang, display) = self
+            .get_with_lang(key.clone(), lang)
+            .ok_or_else(|| format!("missing i18n `{key}`"))?;
+        Message::parse(display)
+            .map_err(|(_, message)| message)
+            .and_then(|message| message.format(&display_lang.id, args))
+            .map_err(|message| format!("invalid i18n `{key}`: {message}"))
+    }
+
+    pub fn iter_ids(&self) -> impl Iterator<Item = &IString> + '_ {
+        self.i18ns.keys()
+    }
+
+    pub fn contains(&self, key: IString) -> bool {
+        self.i18ns.contains_key(&key)
+    }
+
+    fn get(&self, key: IString, lang: Language) -> Option<&IString> {
+        self.get_with_lang(key, lang).map(|(_, display)| display)
+    }
+
+    /// Display of the i18n `key` in `lang` or its fallbacks, along with the language it is in.
+    fn get_with_lang(&self, key: IString, lang: Language) -> Option<(Language, &IString)> {
+        self.i18ns.get_ref(&key).map(|i18n| {
+            lang.chain()
+                .find_map(|lang| Some((lang.clone(), i18n.displays.get_ref(&lang)?)))
+                .unwrap_or((self.default_lang.clone(), &i18n.default_display))
+        })
+    }
+}
+
+impl Index<(IString, Language)> for I18nStore {
+    type Output = IString;
+
+    fn index(&self, (key, lang): (IString, Language)) -> &IString {
+        self.get(key.clone(), lang)
+            .unwrap_or_else(|| panic!("requested key {key} not found"))
+    }
+}
+
+impl Index<(Language, IString)> for I18nStore {
+    type Output = IString;
+
+    fn index(&self, (lang, key): (Language, IString)) -> &IString {
+        &self[(key, lang)]
+    }
+}
+
+pub fn process_i18n(
+    i18n_file_path: IPath,
+    languages: LanguageStore,
+    diagnostics: &mut Diagnostics,
+) -> I18nStore {
+    let Ok((file, table)) =
+        TomlSource::read::<BTreeMap<String, Spanned<SpannedTable>>>(i18n_file_path)
+            .map_err(|err| diagnostics.push(err))
+    else {
+        return I18nStore {
+            i18ns: IMap::default(),
+            default_lang: languages.default.clone(),
+        };
+    };
+    I18nStore {
+        i18ns: table
+            .into_iter()
+            .filter_map(|(i18n_id, table)| {
+                let i18n_id: IString = i18n_id.into();
+                let span = table.span();
+                let mut table = table.into_inner();
+                let default_span = table.get("default").map(|value| value.span());
+                let default_display: IString = file
+                    .take(&mut table, "default", Some(span.clone()))
+                    .map_err(|err| diagnostics.push(err))
+                    .ok()?;
+                let default_args = parse_message(&file, default_span, &default_display)
+                    .map_err(|err| diagnostics.push(err))
+                    .ok()?
+                    .args()
+                    .into_iter()
+                    .map(str::to_owned)
+                    .collect_vec();
+                let displays = table
+                    .into_iter()
+                    .filter_map(|(lang_id, display)| {
+                        let lang =
+                            file.language(&languages, &lang_id, display.span(), diagnostics)?;
+                        let display_span = display.span();
+                        let display: IString = file
+                            .convert(&lang_id, display)
+                            .map_err(|err| diagnostics.push(err))
+                            .ok()?;
+                        let message = parse_message(&file, Some(display_span.clone()), &display)
+                            .map_err(|err| diagnostics.push(err))
+                            .ok()?;
+                        // translations can only use the values given for the default display
+                        if let Some(arg) = message
+                            .args()
+                            .into_iter()
+                            .find(|arg| !default_args.iter().any(|default| default == arg))
+                        {
+                            diagnostics.push(file.error(
+                                ErrorKind::InvalidValue,
+                                Some(display_span),
+                                format!("placeholder `{arg}` is not used by `default`"),
+                            ));
+                            return None;
+                        }
+                        Some((lang, display))
+                    })
+                    .collect();
+                Some((
+                    i18n_id,
+                    I18n {
+                        default_display,
+                        displays,
+                    },
+                ))
+            })
+            .collect(),
+        default_lang: languages.default.clone(),
+    }
+}
+
+/// Parse the `display` located at `span` of the i18n `file`, to report invalid messages early.
+fn parse_message(
+    file: &TomlSource,
+    span: Option<Range<usize>>,
+    display: &str,
+) -> Result<Message, Error> {
+    Message::parse(display).map_err(|(offset, message)| {
+        // skip the opening quote, exact unless the string has escapes
+        let span = span.map(|span| {
+            let start = (span.start + 1 + offset).min(span.end);
+            start..start
+        });
+        file.error(
+            ErrorKind::InvalidValue,
+            span,
+            format!("invalid message: {message}"),
+        )
+    })
+}
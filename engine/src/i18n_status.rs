@@ -0,0 +1,228 @@
+use std::fmt::Write;
+
+use implicit_clone::sync::IString;
+use itertools::Itertools;
+
+use crate::i18n::I18nStore;
+use crate::language::Language;
+use crate::language::LanguageStore;
+use crate::meta::MetaStore;
+use crate::page::PageStore;
+use crate::sync::path::IPath;
+use crate::tag::TagStore;
+use crate::utils::escape_xml;
+
+/// Things to translate of some kind, along with the ones not translated yet.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    pub total: usize,
+    pub missing: Vec<IString>,
+}
+
+impl Coverage {
+    pub fn translated(&self) -> usize {
+        self.total - self.missing.len()
+    }
+
+    /// Percentage of translated things, rounded down (`100` if there is nothing to translate).
+    pub fn percent(&self) -> usize {
+        match self.total {
+            0 => 100,
+            total => self.translated() * 100 / total,
+        }
+    }
+
+    fn push(&mut self, name: impl Into<IString>, translated: bool) {
+        self.total += 1;
+        if !translated {
+            self.missing.push(name.into());
+        }
+    }
+}
+
+/// Translation status of a language other than the default one.
+#[derive(Debug, Clone)]
+pub struct LanguageStatus {
+    pub lang: Language,
+    /// Pages without a page file in the language, drafts aside.
+    pub pages: Coverage,
+    /// Titles and descriptions of metas falling back to the default ones, drafts aside.
+    pub metas: Coverage,
+    /// Titles and descriptions of tags falling back to the default ones.
+    pub tags: Coverage,
+    /// I18n keys falling back to `default`.
+    pub i18ns: Coverage,
+}
+
+impl LanguageStatus {
+    pub fn categories(&self) -> [(&'static str, &Coverage); 4] {
+        [
+            ("pages", &self.pages),
+            ("page titles and descriptions", &self.metas),
+            ("tag titles and descriptions", &self.tags),
+            ("i18n keys", &self.i18ns),
+        ]
+    }
+
+    /// Coverage of all the categories together.
+    pub fn total(&self) -> Coverage {
+        Coverage {
+            total: self.categories().iter().map(|(_, c)| c.total).sum(),
+            missing: self
+                .categories()
+                .iter()
+                .flat_map(|(_, c)| c.missing.clone())
+                .collect(),
+        }
+    }
+}
+
+/// Find what is not translated to every language other than the default one.
+pub fn i18n_status(
+    languages: &LanguageStore,
+    i18ns: &I18nStore,
+    tags: &TagStore,
+    metas: &MetaStore,
+    pages: &PageStore,
+) -> Vec<LanguageStatus> {
+    languages
+        .iter()
+        .filter(|lang| **lang != languages.default)
+        .map(|lang| {
+            let mut status = LanguageStatus {
+                lang: lang.clone(),
+                pages: Coverage::default(),
+                metas: Coverage::default(),
+                tags: Coverage::default(),
+                i18ns: Coverage::default(),
+            };
+            // drafts are not expected to be translated yet
+            let is_draft = |path: IPath| {
+                metas
+                    .get(path)
+                    .is_some_and(|meta| meta.is_draft(lang.clone()))
+            };
+            for (path_id, page) in pages.iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+                if is_draft(path_id.clone()) {
+                    continue;
+                }
+                let name = format!("/{}", path_id.into_iter_lossy().join("/"));
+                status.pages.push(name, page.contains_key(lang));
+            }
+            for meta in metas.iter().sorted_by(|a, b| a.path.cmp(&b.path)) {
+                if is_draft(meta.path.clone()) {
+                    continue;
+                }
+                let name = format!("/{}", meta.path.into_iter_lossy().join("/"));
+                let info = meta.infos.get(lang);
+                let title = info.as_ref().and_then(|info| info.title.clone());
+                let description = info.as_ref().and_then(|info| info.description.clone());
+                status.metas.push(format!("{name} title"), title.is_some());
+                status
+                    .metas
+                    .push(format!("{name} description"), description.is_some());
+            }
+            for tag in tags
+                .iter()
+                .unique_by(|tag| tag.id.clone())
+                .sorted_by(|a, b| a.id.cmp(&b.id))
+            {
+                let info = tag.infos.get(lang);
+                let title = info.as_ref().and_then(|info| info.title.clone());
+                let description = info.as_ref().and_then(|info| info.description.clone());
+                status
+                    .tags
+                    .push(format!("{} title", tag.id), title.is_some());
+                status
+                    .tags
+                    .push(format!("{} description", tag.id), description.is_some());
+            }
+            for (key, i18n) in i18ns.i18ns.iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+                status
+                    .i18ns
+                    .push(key.clone(), i18n.displays.contains_key(lang));
+            }
+            status
+        })
+        .collect()
+}
+
+/// Plain text report of `statuses`, listing everything not translated.
+pub fn status_report(statuses: &[LanguageStatus]) -> String {
+    let mut report = String::new();
+    for status in statuses {
+        let total = status.total();
+        let _ = writeln!(
+            report,
+            "{} ({}): {}% translated ({}/{})",
+            status.lang.id,
+            status.lang.display,
+            total.percent(),
+            total.translated(),
+            total.total
+        );
+        for (name, coverage) in status.categories() {
+            let _ = writeln!(
+                report,
+                "  {name}: {}% ({}/{})",
+                coverage.percent(),
+                coverage.translated(),
+                coverage.total
+            );
+            for missing in &coverage.missing {
+                let _ = writeln!(report, "    missing `{missing}`");
+            }
+        }
+    }
+    report
+}
+
+/// Standalone HTML page of `statuses`, listing everything not translated.
+pub fn status_html(statuses: &[LanguageStatus]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>Translation status</title>\n\
+         <style>\n\
+         body { font-family: sans-serif; max-width: 60em; margin: auto; }\n\
+         progress { width: 10em; }\n\
+         td { padding: 0.25em 1em 0.25em 0; vertical-align: top; }\n\
+         </style>\n\
+         </head>\n\
+         <body>\n\
+         <h1>Translation status</h1>\n",
+    );
+    for status in statuses {
+        let total = status.total();
+        let _ = writeln!(
+            html,
+            "<h2>{} ({}): {}%</h2>\n<table>",
+            escape_xml(&status.lang.display),
+            escape_xml(&status.lang.id),
+            total.percent()
+        );
+        for (name, coverage) in status.categories() {
+            let _ = writeln!(
+                html,
+                "<tr><td>{name}</td>\
+                 <td><progress max=\"{}\" value=\"{}\"></progress> {}% ({}/{})</td>\
+                 <td>{}</td></tr>",
+                coverage.total.max(1),
+                coverage.translated(),
+                coverage.percent(),
+                coverage.translated(),
+                coverage.total,
+                coverage
+                    .missing
+                    .iter()
+                    .map(|missing| format!("<code>{}</code>", escape_xml(missing)))
+                    .join("<br>")
+            );
+        }
+        html.push_str("</table>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
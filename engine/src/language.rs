@@ -1,78 +1,327 @@
-use std::fs;
-
-use implicit_clone::sync::IMap;
-use implicit_clone::sync::IMapValues;
-use implicit_clone::sync::IString;
-use implicit_clone::ImplicitClone;
-
-use crate::sync::path::IPath;
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Language {
-    pub id: IString,
-    pub display: IString,
-}
-
-impl ImplicitClone for Language {}
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct LanguageStore {
-    pub default: Language,
-    pub languages: IMap<IString, Language>,
-}
-
-impl LanguageStore {
-    pub fn get(&self, id: IString) -> Result<Language, Language> {
-        self.languages.get(&id).ok_or_else(|| self.default.clone())
-    }
-
-    pub fn iter(&self) -> impl Iterator<Item = &Language> + '_ {
-        self.languages.values()
-    }
-
-    pub fn iter_ids(&self) -> impl Iterator<Item = &IString> + '_ {
-        self.languages.keys()
-    }
-}
-
-impl ImplicitClone for LanguageStore {}
-
-impl<'a> IntoIterator for &'a LanguageStore {
-    type Item = Language;
-
-    type IntoIter = std::iter::Cloned<IMapValues<'a, IString, Language>>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        self.languages.values().cloned()
-    }
-}
-
-pub fn process_languages(languages_file_path: IPath) -> LanguageStore {
-    let mut table =
-        toml::from_str::<toml::Table>(&fs::read_to_string(languages_file_path).unwrap()).unwrap();
-    let default_id: IString = table
-        .remove("default")
-        .expect("missing `default` language setting in languages file")
-        .try_into()
-        .unwrap();
-    let languages = table
-        .into_iter()
-        .map(|(id, display)| {
-            let id: IString = id.into();
-            (
-                id.clone(),
-                Language {
-                    id,
-                    display: display.try_into().unwrap(),
-                },
-            )
-        })
-        .collect::<IMap<_, _>>();
-    LanguageStore {
-        default: languages
-            .get(&default_id)
-            .expect("`default` language setting in the languages file is invalid")
-            .clone(),
-        languages,
-    }
-}
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::ops::Range;
+
+use implicit_clone::sync::IArray;
+use implicit_clone::sync::IMap;
+use implicit_clone::sync::IMapValues;
+use implicit_clone::sync::IString;
+use implicit_clone::ImplicitClone;
+use serde::de::IgnoredAny;
+use serde::de::MapAccess;
+use serde::de::SeqAccess;
+use serde::de::Visitor;
+use serde::Deserialize;
+use serde::Deserializer;
+use toml::Spanned;
+
+use crate::error::Diagnostics;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::sync::path::IPath;
+use crate::utils::SpannedTable;
+use crate::utils::TomlSource;
+
+/// Languages are identified by their `id` only.
+#[derive(Debug, Clone)]
+pub struct Language {
+    pub id: IString,
+    pub display: IString,
+    /// BCP-47 locale code (e.g. `en-US`), for `<html lang>` and `hreflang`.
+    pub locale: IString,
+    pub direction: Direction,
+    /// Format of dates, see [`Date::format`](crate::date::Date::format).
+    pub date_format: IString,
+    /// Languages to use, in order, for what is missing in this one. Ends with the default language
+    /// (empty for the default language itself).
+    pub fallbacks: IArray<Language>,
+}
+
+impl ImplicitClone for Language {}
+
+impl PartialEq for Language {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Language {}
+
+impl Hash for Language {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl Language {
+    /// The language followed by its [`Language::fallbacks`].
+    pub fn chain(&self) -> impl Iterator<Item = Language> + '_ {
+        std::iter::once(self.clone()).chain(self.fallbacks.iter().cloned())
+    }
+}
+
+/// Text direction of a language.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    /// Value of the HTML `dir` attribute.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::Ltr => "ltr",
+            Direction::Rtl => "rtl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageStore {
+    pub default: Language,
+    pub languages: IMap<IString, Language>,
+    /// Whether pages missing in a language are rendered in it from the default language.
+    pub fallback_pages: bool,
+}
+
+impl LanguageStore {
+    /// Language by its id. What is missing in a language falls back along its
+    /// [`Language::chain`] instead.
+    pub fn get(&self, id: IString) -> Option<Language> {
+        self.languages.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Language> + '_ {
+        self.languages.values()
+    }
+
+    pub fn iter_ids(&self) -> impl Iterator<Item = &IString> + '_ {
+        self.languages.keys()
+    }
+}
+
+impl ImplicitClone for LanguageStore {}
+
+impl<'a> IntoIterator for &'a LanguageStore {
+    type Item = Language;
+
+    type IntoIter = std::iter::Cloned<IMapValues<'a, IString, Language>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.languages.values().cloned()
+    }
+}
+
+pub fn process_languages(
+    languages_file_path: IPath,
+    diagnostics: &mut Diagnostics,
+) -> Option<LanguageStore> {
+    let (file, mut table) = TomlSource::read::<SpannedTable>(languages_file_path)
+        .map_err(|err| diagnostics.push(err))
+        .ok()?;
+    // values in tables have no spans, so the ones of fallbacks are read separately
+    let fallback_spans =
+        toml::from_str::<BTreeMap<String, FallbackSpan>>(&file.source).unwrap_or_default();
+    let default_span = table.get("default").map(|value| value.span());
+    let default_id = file
+        .take::<IString>(&mut table, "default", None)
+        .map_err(|err| diagnostics.push(err))
+        .ok();
+    let fallback_pages = table
+        .remove("fallback_pages")
+        .map(|value| file.convert("fallback_pages", value))
+        .transpose()
+        .map_err(|err| diagnostics.push(err))
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    // languages along with the language they fall back to
+    let languages = table
+        .into_iter()
+        .filter_map(|(id, value)| {
+            let fallback_span = fallback_spans.get(&id).and_then(|span| span.0.clone());
+            let (language, fallback) =
+                process_language(&file, id.clone().into(), value, fallback_span)
+                    .map_err(|err| diagnostics.push(err))
+                    .ok()?;
+            Some((language.id.clone(), (language, fallback)))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let Some((default, _)) = default_id.and_then(|id| languages.get(&id)) else {
+        diagnostics.push(file.error(
+            ErrorKind::UnknownLanguage,
+            default_span,
+            "`default` language setting in the languages file is invalid",
+        ));
+        return None;
+    };
+    let languages = languages
+        .iter()
+        .map(|(id, (language, fallback))| {
+            let mut fallbacks = Vec::new();
+            if language == default {
+                if let Some(fallback) = fallback {
+                    diagnostics.push(file.error(
+                        ErrorKind::InvalidValue,
+                        Some(fallback.span()),
+                        "the default language can not fall back to another one",
+                    ));
+                }
+            } else {
+                let mut next = fallback.clone();
+                loop {
+                    let (next_id, span) = match next {
+                        Some(next) => (next.get_ref().clone(), Some(next.span())),
+                        None => (default.id.clone(), None),
+                    };
+                    let Some((next_language, next_fallback)) = languages.get(&next_id) else {
+                        diagnostics.push(file.error(
+                            ErrorKind::UnknownLanguage,
+                            span,
+                            format!("fallback language `{next_id}` is not defined in the languages file"),
+                        ));
+                        fallbacks.push(default.clone());
+                        break;
+                    };
+                    if next_language == language || fallbacks.contains(next_language) {
+                        diagnostics.push(file.error(
+                            ErrorKind::InvalidValue,
+                            span,
+                            format!("fallbacks of `{id}` form a loop"),
+                        ));
+                        fallbacks.push(default.clone());
+                        break;
+                    }
+                    fallbacks.push(next_language.clone());
+                    if next_language == default {
+                        break;
+                    }
+                    next = next_fallback.clone();
+                }
+            }
+            (
+                id.clone(),
+                Language {
+                    fallbacks: fallbacks.into(),
+                    ..language.clone()
+                },
+            )
+        })
+        .collect::<IMap<_, _>>();
+    Some(LanguageStore {
+        default: languages.get(&default.id)?,
+        languages,
+        fallback_pages,
+    })
+}
+
+/// Settings of a language given as a table.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LanguageTable {
+    display: IString,
+    locale: Option<IString>,
+    direction: Option<Direction>,
+    date_format: Option<IString>,
+    fallback: Option<IString>,
+}
+
+/// Span of the `fallback` of a language, if it is given by a table setting it.
+#[derive(Debug, Default)]
+struct FallbackSpan(Option<Range<usize>>);
+
+impl<'de> Deserialize<'de> for FallbackSpan {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FallbackSpanVisitor)
+    }
+}
+
+/// Visitor of any value, looking for the `fallback` key in tables.
+struct FallbackSpanVisitor;
+
+impl<'de> Visitor<'de> for FallbackSpanVisitor {
+    type Value = FallbackSpan;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<FallbackSpan, E> {
+        Ok(FallbackSpan(None))
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<FallbackSpan, E> {
+        Ok(FallbackSpan(None))
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<FallbackSpan, E> {
+        Ok(FallbackSpan(None))
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<FallbackSpan, E> {
+        Ok(FallbackSpan(None))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<FallbackSpan, A::Error> {
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(FallbackSpan(None))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<FallbackSpan, A::Error> {
+        let mut span = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "fallback" => span = Some(map.next_value::<Spanned<IgnoredAny>>()?.span()),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(FallbackSpan(span))
+    }
+}
+
+/// Language given either by its display (`en = "English"`) or by a table with the display and
+/// other settings. Returned along with the id of the language it falls back to, if it is set,
+/// located at `fallback_span` (or at the table if it is unknown).
+fn process_language(
+    file: &TomlSource,
+    id: IString,
+    value: Spanned<toml::Value>,
+    fallback_span: Option<Range<usize>>,
+) -> Result<(Language, Option<Spanned<IString>>), Error> {
+    let span = fallback_span.unwrap_or(value.span());
+    let table = match value.get_ref() {
+        toml::Value::Table(_) => file.convert::<LanguageTable>(&id, value)?,
+        _ => {
+            let display = file.convert(&id, value)?;
+            return Ok((default_language(id, display), None));
+        }
+    };
+    let language = default_language(id, table.display);
+    Ok((
+        Language {
+            locale: table.locale.unwrap_or(language.locale.clone()),
+            direction: table.direction.unwrap_or_default(),
+            date_format: table.date_format.unwrap_or(language.date_format.clone()),
+            ..language
+        },
+        table.fallback.map(|fallback| Spanned::new(span, fallback)),
+    ))
+}
+
+fn default_language(id: IString, display: IString) -> Language {
+    Language {
+        locale: id.clone(),
+        id,
+        display,
+        direction: Direction::Ltr,
+        date_format: "%Y-%m-%d".into(),
+        fallbacks: IArray::EMPTY,
+    }
+}
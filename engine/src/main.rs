@@ -1,275 +1,1055 @@
-#![allow(clippy::duplicate_mod)]
-
-pub mod i18n;
-pub mod language;
-pub mod meta;
-pub mod page;
-pub mod render;
-pub mod sync;
-pub mod tag;
-pub mod template;
-pub mod unsync;
-pub mod utils;
-
-use std::fs;
-use std::io::Cursor;
-
-use comrak::plugins::syntect::SyntectAdapterBuilder;
-use comrak::ExtensionOptions;
-use comrak::Options;
-use comrak::Plugins;
-use comrak::RenderOptions;
-use comrak::RenderPlugins;
-use implicit_clone::sync::IArray;
-use implicit_clone::sync::IString;
-use itertools::Itertools;
-
-use crate::i18n::process_i18n;
-use crate::language::process_languages;
-use crate::meta::process_metas;
-use crate::page::process_pages;
-use crate::render::my_render;
-use crate::render::RenderCtx;
-use crate::sync::path::IPath;
-use crate::tag::process_tags;
-use crate::template::process_templates;
-use crate::template::Context;
-use crate::template::PageMeta;
-
-fn main() {
-    run(IPath::new("secdb"), IPath::new("public/secdb"));
-}
-
-fn run(src_dir_path: IPath, public_dir_path: IPath) {
-    let languages = process_languages(src_dir_path.join("languages.toml"));
-    println!(
-        "languages: [{}]",
-        languages.iter_ids().map(|id| format!("`{id}`")).join(", ")
-    );
-
-    let i18ns = process_i18n(src_dir_path.join("i18n.toml"), languages.clone());
-    println!(
-        "i18ns: [{}]",
-        i18ns.iter_ids().map(|id| format!("`{id}`")).join(", ")
-    );
-
-    let tags = process_tags(src_dir_path.join("tags"), languages.clone());
-    println!(
-        "tags: [{}]",
-        tags.iter_ids().map(|id| format!("`{id}`")).join(", ")
-    );
-
-    let pages_dir_path = src_dir_path.join("pages");
-    let metas = process_metas(pages_dir_path.clone(), languages.clone(), tags.clone());
-    println!(
-        "metas: [{}]",
-        metas
-            .iter_ids()
-            .map(|id| format!("`{}`", id.display()))
-            .join(", ")
-    );
-
-    let pages = process_pages(pages_dir_path.clone(), languages.clone(), metas.clone());
-    let templates = process_templates(
-        src_dir_path.join("templates"),
-        languages.clone(),
-        i18ns.clone(),
-        tags.clone(),
-        metas.clone(),
-    );
-    // do not forget to update [engine/README.md] for used templates
-
-    fs::create_dir_all(&public_dir_path).unwrap();
-    let options = Options {
-        extension: ExtensionOptions {
-            header_ids: Some("".to_string()),
-            ..Default::default()
-        },
-        render: RenderOptions {
-            unsafe_: true,
-            ..Default::default()
-        },
-        ..Default::default()
-    };
-    let highlighter = SyntectAdapterBuilder::new()
-        .theme_set(syntect::highlighting::ThemeSet {
-            themes: [(
-                "darcula".into(),
-                syntect::highlighting::ThemeSet::load_from_reader(&mut Cursor::new(include_str!(
-                    "../../public/assets/darcula.tmTheme"
-                )))
-                .unwrap(),
-            )]
-            .into(),
-        })
-        .theme("darcula")
-        .build();
-    let plugins = Plugins {
-        render: RenderPlugins {
-            codefence_syntax_highlighter: Some(&highlighter),
-            ..Default::default()
-        },
-    };
-
-    // write all pages
-    for (path_id, page_lang_path_map) in pages.iter() {
-        let meta = metas
-            .get(path_id.clone())
-            .unwrap_or_else(|| panic!("missing page meta for page `{}`", path_id.display()));
-        let path_id: IString = path_id.into_iter_lossy().join("/").into();
-
-        let available_languages = page_lang_path_map
-            .keys()
-            .map(|lang| lang.id.clone())
-            .collect::<IArray<_>>();
-
-        for (lang, index_filepath) in page_lang_path_map.iter() {
-            let info = meta.info(lang.clone());
-            let context = Context {
-                current_lang: lang.clone(),
-                current_tag: None,
-                pages: Default::default(),
-
-                languages: languages.clone(),
-                tags: tags.clone(),
-
-                page: PageMeta {
-                    path: path_id.clone(),
-                    tags: meta.tags.iter().map(|tag| tag.id.clone()).collect(),
-                    available_in_lang: true,
-                    languages: available_languages.clone(),
-                },
-                title: Some(info.title.clone()),
-                description: Some(info.description.clone()),
-            };
-            let content =
-                templates.compile_and_render(index_filepath.clone(), context.clone(), None);
-            let content = templates.render("page".into(), context.clone(), Some(content));
-            let content = templates.render("layout".into(), context, Some(content));
-
-            let path = public_dir_path
-                .join(&*lang.id)
-                .join(&*path_id)
-                .join("index.html");
-            fs::create_dir_all(path.parent().unwrap()).unwrap();
-            my_render(
-                path,
-                content,
-                RenderCtx {
-                    lang: lang.clone(),
-                    i18ns: i18ns.clone(),
-                    metas: metas.clone(),
-                    pages: pages.clone(),
-                },
-                &options,
-                &plugins,
-            );
-        }
-    }
-
-    // write the "all tags" pages
-    for lang in languages.iter() {
-        let context = Context {
-            current_lang: lang.clone(),
-            current_tag: None,
-            pages: Default::default(),
-
-            languages: languages.clone(),
-            tags: tags.clone(),
-
-            page: PageMeta {
-                path: "tags".into(),
-                tags: Default::default(),
-                available_in_lang: true,
-                languages: languages.iter_ids().cloned().collect(),
-            },
-            title: Some(
-                i18ns
-                    .display("all_tags".into(), lang.clone())
-                    .unwrap_or_else(|| panic!("missing i18n for all_tags"))
-                    .clone(),
-            ),
-            description: None,
-        };
-        let content = templates.render("tags".into(), context.clone(), None);
-        let content = templates.render("layout".into(), context, Some(content));
-        let path = public_dir_path.join(&*lang.id).join("tags/index.html");
-        fs::create_dir_all(path.parent().unwrap()).unwrap();
-        my_render(
-            path,
-            content,
-            RenderCtx {
-                lang: lang.clone(),
-                i18ns: i18ns.clone(),
-                metas: metas.clone(),
-                pages: pages.clone(),
-            },
-            &options,
-            &plugins,
-        );
-    }
-
-    // write all tag pages
-    for lang in languages.iter() {
-        for tag_id in tags.iter_ids().cloned() {
-            let tag = tags.get(tag_id.clone()).unwrap();
-            let content = match tag.id == tag_id {
-                true => {
-                    let context = Context {
-                        current_lang: lang.clone(),
-                        current_tag: Some(tag.clone()),
-                        pages: metas
-                            .iter_by_tag(tag.clone())
-                            .map(|meta| {
-                                let available_langs = pages
-                                    .get(meta.path.clone())
-                                    .unwrap()
-                                    .keys()
-                                    .cloned()
-                                    .collect();
-                                (meta.clone(), available_langs)
-                            })
-                            .collect(),
-
-                        languages: languages.clone(),
-                        tags: tags.clone(),
-
-                        page: PageMeta {
-                            path: format!("tags/{tag_id}").into(),
-                            tags: Default::default(),
-                            available_in_lang: true,
-                            languages: languages.iter_ids().cloned().collect(),
-                        },
-                        title: Some(tag.title(lang.clone())),
-                        description: Some(tag.description(lang.clone())),
-                    };
-                    let content = templates.render("tag".into(), context.clone(), None);
-                    templates.render("layout".into(), context, Some(content))
-                }
-                false => format!(
-                    "<meta http-equiv=\"refresh\" content=\"0; url=/{}/tags/\">",
-                    lang.id
-                )
-                .into(),
-            };
-            let path = public_dir_path
-                .join(&*lang.id)
-                .join("tags")
-                .join(&*tag_id)
-                .join("index.html");
-            fs::create_dir_all(path.parent().unwrap()).unwrap();
-            my_render(
-                path,
-                content,
-                RenderCtx {
-                    lang: lang.clone(),
-                    i18ns: i18ns.clone(),
-                    metas: metas.clone(),
-                    pages: pages.clone(),
-                },
-                &options,
-                &plugins,
-            );
-        }
-    }
-}
+#![allow(clippy::duplicate_mod)]
+
+pub mod cache;
+pub mod check;
+pub mod cli;
+pub mod code;
+pub mod date;
+pub mod diagram;
+pub mod error;
+pub mod feed;
+pub mod front_matter;
+pub mod git;
+pub mod i18n;
+pub mod i18n_status;
+pub mod language;
+pub mod math;
+pub mod message;
+pub mod meta;
+pub mod page;
+pub mod render;
+pub mod search;
+pub mod serve;
+pub mod sitemap;
+pub mod sync;
+pub mod tag;
+pub mod template;
+pub mod unsync;
+pub mod utils;
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Cursor;
+use std::io::Write;
+use std::process;
+
+use clap::Parser;
+use comrak::plugins::syntect::SyntectAdapterBuilder;
+use comrak::ExtensionOptions;
+use comrak::Options;
+use comrak::Plugins;
+use comrak::RenderOptions;
+use comrak::RenderPlugins;
+use implicit_clone::sync::IString;
+use itertools::Itertools;
+
+use crate::cache::engine_version;
+use crate::cache::hash_files;
+use crate::cache::hash_stores;
+use crate::cache::hash_value;
+use crate::cache::BuildCache;
+use crate::cache::Inputs;
+use crate::check::check_i18n_usages;
+use crate::check::check_links;
+use crate::check::collect_links;
+use crate::check::read_link_allowlist;
+use crate::check::LinkCtx;
+use crate::check::OutputLinks;
+use crate::cli::Args;
+use crate::cli::CheckArgs;
+use crate::cli::Cli;
+use crate::cli::Command;
+use crate::cli::I18nStatusArgs;
+use crate::date::Date;
+use crate::error::Diagnostics;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::feed::Feed;
+use crate::feed::FeedEntry;
+use crate::git::GitDates;
+use crate::i18n::process_i18n;
+use crate::i18n::I18nStore;
+use crate::i18n_status::i18n_status;
+use crate::i18n_status::status_html;
+use crate::i18n_status::status_report;
+use crate::language::process_languages;
+use crate::language::Language;
+use crate::language::LanguageStore;
+use crate::meta::process_metas;
+use crate::meta::Meta;
+use crate::meta::MetaStore;
+use crate::page::process_pages;
+use crate::page::PageStore;
+use crate::render::linked_pages;
+use crate::render::my_render;
+use crate::render::table_of_contents;
+use crate::render::RenderCtx;
+use crate::search::search_index;
+use crate::search::SearchPage;
+use crate::serve::serve;
+use crate::sitemap::robots_txt;
+use crate::sitemap::sitemap;
+use crate::sitemap::SitemapPage;
+use crate::sync::path::IPath;
+use crate::sync::path::ToIPath;
+use crate::tag::process_tags;
+use crate::tag::TagStore;
+use crate::template::process_templates;
+use crate::template::Backlink;
+use crate::template::Context;
+use crate::template::PageMeta;
+use crate::template::TemplateStore;
+use crate::utils::parallel_map;
+use crate::utils::Info;
+
+fn main() {
+    let Cli { args, command } = Cli::parse();
+    let mut diagnostics = Diagnostics::default();
+    match command.unwrap_or_default() {
+        Command::Build => run(&args, None, &mut diagnostics),
+        Command::Check(check_args) => {
+            run(&args, Some(&check_args), &mut diagnostics);
+            if diagnostics.is_empty() && args.verbosity() >= 1 {
+                println!("no problems found");
+            }
+        }
+        Command::Clean => clean(&args, &mut diagnostics),
+        Command::I18nStatus(status_args) => {
+            if let Some(site) = load(&args, &mut diagnostics) {
+                i18n_status_report(&site, &status_args, &mut diagnostics);
+            }
+        }
+        Command::List => {
+            if let Some(site) = load(&args, &mut diagnostics) {
+                list(&site);
+            }
+        }
+        Command::Serve(serve_args) => {
+            // previews show drafts too
+            let args = Args {
+                drafts: true,
+                ..args
+            };
+            run(&args, None, &mut diagnostics);
+            diagnostics.report();
+            // problems of the builds are reported as they happen, only failures to serve are left
+            diagnostics = Diagnostics::default();
+            let served = serve(
+                (&serve_args.host, serve_args.port),
+                args.out.to_ipath(),
+                args.base_path(),
+                args.src.to_ipath(),
+                || {
+                    let mut diagnostics = Diagnostics::default();
+                    run(&args, None, &mut diagnostics);
+                    diagnostics.report();
+                },
+            );
+            if let Err(err) = served {
+                diagnostics.push(err);
+            }
+        }
+    }
+    diagnostics.report();
+    if !diagnostics.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// Output file to be generated.
+struct Output<'a> {
+    path: IPath,
+    /// File the problems in the content are reported for.
+    source: IPath,
+    inputs: Inputs,
+    lang: Language,
+    /// Whether the content is written as is, instead of being rendered as markdown.
+    raw: bool,
+    content: Box<dyn Fn() -> Result<IString, Error> + Send + Sync + 'a>,
+}
+
+/// All stores of a source directory.
+struct Site {
+    languages: LanguageStore,
+    i18ns: I18nStore,
+    tags: TagStore,
+    metas: MetaStore,
+    pages: PageStore,
+    templates: TemplateStore,
+}
+
+/// Load all stores. Only fails if the languages could not be loaded, as nothing can be done
+/// without them; other problems are collected into `diagnostics`.
+fn load(args: &Args, diagnostics: &mut Diagnostics) -> Option<Site> {
+    let src_dir_path = args.src.to_ipath();
+
+    let languages = process_languages(src_dir_path.join("languages.toml"), diagnostics)?;
+    let i18ns = process_i18n(
+        src_dir_path.join("i18n.toml"),
+        languages.clone(),
+        diagnostics,
+    );
+    let tags = process_tags(src_dir_path.join("tags"), languages.clone(), diagnostics);
+
+    let pages_dir_path = src_dir_path.join("pages");
+    let metas = process_metas(
+        pages_dir_path.clone(),
+        languages.clone(),
+        tags.clone(),
+        diagnostics,
+    );
+    let pages = process_pages(
+        pages_dir_path.clone(),
+        languages.clone(),
+        metas.clone(),
+        diagnostics,
+    );
+    // everything else only sees the pages that are built
+    let pages = match args.drafts {
+        true => pages,
+        false => pages.without_drafts(metas.clone()),
+    };
+    let templates = process_templates(
+        src_dir_path.join("templates"),
+        args.base_url().into(),
+        languages.clone(),
+        i18ns.clone(),
+        tags.clone(),
+        metas.clone(),
+        diagnostics,
+    );
+    // do not forget to update [engine/README.md] for used templates
+
+    Some(Site {
+        languages,
+        i18ns,
+        tags,
+        metas,
+        pages,
+        templates,
+    })
+}
+
+fn list(
+    Site {
+        languages,
+        i18ns,
+        tags,
+        metas,
+        pages,
+        ..
+    }: &Site,
+) {
+    println!(
+        "languages: [{}]",
+        languages.iter_ids().map(|id| format!("`{id}`")).join(", ")
+    );
+    println!(
+        "i18ns: [{}]",
+        i18ns.iter_ids().map(|id| format!("`{id}`")).join(", ")
+    );
+    println!(
+        "tags: [{}]",
+        tags.iter_ids().map(|id| format!("`{id}`")).join(", ")
+    );
+    println!(
+        "metas: [{}]",
+        metas
+            .iter_ids()
+            .map(|id| format!("`{}`", id.display()))
+            .join(", ")
+    );
+    println!(
+        "pages: [{}]",
+        pages
+            .iter()
+            .map(|(id, page)| format!(
+                "`{}` ({})",
+                id.display(),
+                page.keys().map(|lang| lang.id.clone()).join(", ")
+            ))
+            .join(", ")
+    );
+}
+
+fn i18n_status_report(
+    Site {
+        languages,
+        i18ns,
+        tags,
+        metas,
+        pages,
+        ..
+    }: &Site,
+    args: &I18nStatusArgs,
+    diagnostics: &mut Diagnostics,
+) {
+    let statuses = i18n_status(languages, i18ns, tags, metas, pages);
+    print!("{}", status_report(&statuses));
+    if let Some(path) = &args.html {
+        if let Err(err) = fs::write(path, status_html(&statuses)) {
+            diagnostics.push(Error::new(ErrorKind::Io, err.to_string()).with_path(path.to_ipath()));
+        }
+    }
+}
+
+fn clean(args: &Args, diagnostics: &mut Diagnostics) {
+    // the build cache is removed as well, as it is of no use without the outputs, and so is
+    // `robots.txt` written outside of the output directory
+    let robots_txt = args
+        .root_dir()
+        .filter(|root_dir| *root_dir != args.out)
+        .map(|root_dir| root_dir.join("robots.txt"));
+    for (path, result) in [
+        (&args.out, fs::remove_dir_all(&args.out)),
+        (&args.cache, fs::remove_file(&args.cache)),
+    ]
+    .into_iter()
+    .chain(robots_txt.iter().map(|path| (path, fs::remove_file(path))))
+    {
+        match result {
+            Ok(()) => {
+                if args.verbosity() >= 1 {
+                    println!("removed `{}`", path.display());
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => diagnostics.push(
+                Error::new(ErrorKind::Io, format!("failed to remove: {err}"))
+                    .with_path(path.to_ipath()),
+            ),
+        }
+    }
+}
+
+/// Run the whole pipeline. Unless `check`ing, output files are written, but only if their inputs
+/// changed since the previous build (unless forced). When `check`ing, nothing is written, but links
+/// between all outputs are checked.
+fn run(args: &Args, check: Option<&CheckArgs>, diagnostics: &mut Diagnostics) {
+    let write = check.is_none();
+    let Some(site) = load(args, diagnostics) else {
+        return;
+    };
+    if args.verbosity() >= 1 {
+        list(&site);
+    }
+
+    let Site {
+        languages,
+        i18ns,
+        tags,
+        metas,
+        pages,
+        templates,
+    } = site;
+    let public_dir_path = args.out.to_ipath();
+    let base_url: IString = args.base_url().into();
+
+    // i18n keys that are not rendered (e.g. in branches not taken) are still checked
+    for path in templates
+        .iter_paths()
+        .chain(pages.iter().flat_map(|(_, page)| page.values()))
+    {
+        check_i18n_usages(path.clone(), &i18ns, diagnostics);
+    }
+
+    let git = GitDates::read(args.src.to_ipath());
+    let outdated_translations = pages.outdated_translations(languages.default.clone(), &git);
+    if check.is_some() {
+        for (path, source) in outdated_translations.iter() {
+            diagnostics.push(
+                Error::new(
+                    ErrorKind::OutdatedTranslation,
+                    format!(
+                        "translation was last committed before the last change of `{}`",
+                        source.display()
+                    ),
+                )
+                .with_path(path.clone()),
+            );
+        }
+    }
+
+    let options = Options {
+        extension: ExtensionOptions {
+            header_ids: Some("".to_string()),
+            alerts: true,
+            math_dollars: true,
+            ..Default::default()
+        },
+        render: RenderOptions {
+            unsafe_: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let highlighter = SyntectAdapterBuilder::new()
+        .theme_set(syntect::highlighting::ThemeSet {
+            themes: [(
+                "darcula".into(),
+                syntect::highlighting::ThemeSet::load_from_reader(&mut Cursor::new(include_str!(
+                    "../../public/assets/darcula.tmTheme"
+                )))
+                .unwrap(),
+            )]
+            .into(),
+        })
+        .theme("darcula")
+        .build();
+    let plugins = Plugins {
+        render: RenderPlugins {
+            codefence_syntax_highlighter: Some(&highlighter),
+            ..Default::default()
+        },
+    };
+
+    let mut cache = BuildCache::new(
+        args.cache.to_ipath(),
+        public_dir_path.clone(),
+        args.force || !write,
+    );
+    let stores = hash_stores(
+        args.src.to_ipath(),
+        &[args.base_url(), if args.drafts { "drafts" } else { "" }],
+    );
+    let engine = engine_version();
+    // templates can include each other, so outputs rendered with templates depend on all of them
+    let templates_hash = hash_files(templates.iter_paths().sorted());
+    let inputs = |sources: &[IPath], metas: &[IPath], uses_templates: bool| Inputs {
+        source: (!sources.is_empty()).then(|| hash_files(sources)),
+        meta: (!metas.is_empty()).then(|| hash_files(metas)),
+        templates: uses_templates.then(|| templates_hash.clone()),
+        stores: stores.clone(),
+        engine: engine.clone(),
+    };
+    let templates = &templates;
+    let options = &options;
+    let mut outputs = Vec::new();
+    let mut search_pages = Vec::new();
+    let mut feed_entries = Vec::new();
+    let mut sitemap_pages = Vec::new();
+
+    let page_context = |meta: &Meta, lang: Language| {
+        let info = meta.info(lang.clone());
+        Context {
+            current_lang: lang.clone(),
+            current_tag: None,
+            pages: Default::default(),
+
+            languages: languages.clone(),
+            tags: tags.clone(),
+
+            page: PageMeta {
+                path: meta.path.into_iter_lossy().join("/").into(),
+                tags: meta.tags.iter().map(|tag| tag.id.clone()).collect(),
+                available_in_lang: true,
+                content_lang: lang.id.clone(),
+                languages: pages
+                    .get(meta.path.clone())
+                    .unwrap_or_default()
+                    .keys()
+                    .map(|lang| lang.id.clone())
+                    .collect(),
+            },
+            title: Some(info.title),
+            description: Some(info.description),
+            toc: Default::default(),
+            backlinks: Default::default(),
+            draft: meta.is_draft(lang.clone()),
+            outdated_translation: pages
+                .get(meta.path.clone())
+                .and_then(|page| page.get(&lang))
+                .is_some_and(|path| outdated_translations.contains_key(&path)),
+            updated: None,
+        }
+    };
+
+    // find the links between pages in a first pass, so that pages can list the pages linking to
+    // them in the same language
+    let page_variants = pages
+        .iter()
+        .flat_map(|(path_id, page_lang_path_map)| {
+            page_lang_path_map.iter().map(|(lang, index_filepath)| {
+                (path_id.clone(), lang.clone(), index_filepath.clone())
+            })
+        })
+        .collect_vec();
+    let page_links = parallel_map(
+        &page_variants,
+        args.jobs(),
+        |(path_id, lang, index_filepath)| {
+            let context = page_context(&metas[path_id.clone()], lang.clone());
+            templates
+                .compile_and_render(index_filepath.clone(), context, None)
+                .map(|content| linked_pages(&content, options, pages.clone()))
+                .unwrap_or_default()
+        },
+    );
+    let mut backlinks = HashMap::<_, BTreeSet<_>>::new();
+    for ((path_id, lang, _), linked) in page_variants.into_iter().zip(page_links) {
+        for target in linked.into_iter().filter(|target| *target != path_id) {
+            backlinks
+                .entry((target, lang.clone()))
+                .or_default()
+                .insert(path_id.clone());
+        }
+    }
+
+    // write all pages
+    for (path_id, page_lang_path_map) in pages.iter() {
+        let meta = metas[path_id.clone()].clone();
+        let path_id: IString = path_id.into_iter_lossy().join("/").into();
+
+        // languages the page is missing in may get the page file of the first of their fallbacks
+        // the page is available in
+        let fallbacks = languages
+            .iter()
+            .filter(|_| languages.fallback_pages)
+            .filter(|lang| !page_lang_path_map.contains_key(lang))
+            .filter_map(|lang| {
+                lang.fallbacks.iter().find_map(|fallback| {
+                    let index_filepath = page_lang_path_map.get(fallback)?;
+                    Some((lang.clone(), fallback.clone(), index_filepath))
+                })
+            })
+            .collect_vec();
+        let mut variants = Vec::new();
+        for (lang, content_lang, index_filepath) in page_lang_path_map
+            .iter()
+            .map(|(lang, index_filepath)| (lang.clone(), lang.clone(), index_filepath.clone()))
+            .chain(fallbacks)
+        {
+            let fallback = lang != content_lang;
+            let info = meta.info(lang.clone());
+            // explicit dates take precedence over the history of the page file
+            let updated = meta
+                .updated
+                .or_else(|| git.last_commit_date(index_filepath.clone()))
+                .or_else(|| {
+                    let modified = fs::metadata(&index_filepath).and_then(|m| m.modified());
+                    modified.ok().map(Date::from_system_time)
+                })
+                .unwrap_or_default()
+                .max(meta.created.unwrap_or_default());
+            let context = page_context(&meta, lang.clone());
+            let context = Context {
+                page: PageMeta {
+                    available_in_lang: !fallback,
+                    content_lang: content_lang.id.clone(),
+                    ..context.page
+                },
+                draft: meta.is_draft(content_lang.clone()),
+                updated: Some(updated),
+                backlinks: backlinks
+                    .get(&(meta.path.clone(), lang.clone()))
+                    .into_iter()
+                    .flatten()
+                    .map(|path| Backlink {
+                        path: path.into_iter_lossy().join("/").into(),
+                        title: metas.title(path.clone(), lang.clone()).unwrap_or_default(),
+                    })
+                    .collect(),
+                ..context
+            };
+            // fallback pages point search engines to the original, and are not listed anywhere
+            if !fallback {
+                let url: IString = format!("{base_url}/{}/{path_id}", lang.id).into();
+                variants.push((lang.locale.clone(), url.clone(), Some(updated)));
+                feed_entries.push((
+                    lang.clone(),
+                    meta.clone(),
+                    FeedEntry {
+                        url: url.clone(),
+                        title: info.title.clone(),
+                        description: info.description.clone(),
+                        categories: meta
+                            .tags
+                            .iter()
+                            .map(|tag| (tag.id.clone(), tag.title(lang.clone())))
+                            .collect(),
+                        published: meta.created,
+                        updated,
+                    },
+                ));
+                search_pages.push((
+                    lang.clone(),
+                    SearchPage {
+                        url,
+                        filepath: index_filepath.clone(),
+                        context: context.clone(),
+                    },
+                ));
+            }
+            outputs.push(Output {
+                path: public_dir_path
+                    .join(&*lang.id)
+                    .join(&*path_id)
+                    .join("index.html"),
+                source: index_filepath.clone(),
+                // backlinks depend on the content of other pages, outdated translations and dates
+                // on the history of page files
+                inputs: Inputs {
+                    stores: hash_value(&(
+                        &stores,
+                        context.backlinks.as_slice(),
+                        context.outdated_translation,
+                        context.updated,
+                    )),
+                    ..inputs(std::slice::from_ref(&index_filepath), &meta.filepaths, true)
+                },
+                lang: lang.clone(),
+                raw: false,
+                content: Box::new(move || {
+                    let content = templates.compile_and_render(
+                        index_filepath.clone(),
+                        context.clone(),
+                        None,
+                    )?;
+                    let context = Context {
+                        toc: meta
+                            .toc
+                            .map(|depth| table_of_contents(&content, options, depth))
+                            .unwrap_or_default(),
+                        ..context.clone()
+                    };
+                    templates
+                        .render("page".into(), context.clone(), Some(content))
+                        .and_then(|content| {
+                            templates.render("layout".into(), context.clone(), Some(content))
+                        })
+                }),
+            });
+        }
+        sitemap_pages.push(SitemapPage {
+            variants,
+            default_lang: languages.default.locale.clone(),
+        });
+    }
+
+    // write the search indices
+    for lang in languages.iter() {
+        let search_pages = search_pages
+            .iter()
+            .filter(|(page_lang, _)| page_lang == lang)
+            .map(|(_, page)| page.clone())
+            .collect_vec();
+        outputs.push(Output {
+            path: public_dir_path.join(&*lang.id).join("search.json"),
+            source: args.src.to_ipath().join("pages"),
+            inputs: inputs(
+                &search_pages
+                    .iter()
+                    .map(|page| page.filepath.clone())
+                    .collect_vec(),
+                &[],
+                true,
+            ),
+            lang: lang.clone(),
+            raw: true,
+            content: Box::new(move || Ok(search_index(&search_pages, templates, options))),
+        });
+    }
+
+    // write the feeds of all pages and of every tag, which need absolute URLs
+    if !args.is_base_url_absolute() {
+        diagnostics.warn(Error::new(
+            ErrorKind::BaseUrl,
+            format!("feeds are not generated, as the base URL `{base_url}` is not absolute"),
+        ));
+    }
+    for lang in languages.iter().filter(|_| args.is_base_url_absolute()) {
+        let site = metas
+            .get(IPath::default())
+            .map(|meta| meta.info(lang.clone()))
+            .unwrap_or_else(|| Info {
+                title: "".into(),
+                description: "".into(),
+            });
+        let lang_entries = feed_entries
+            .iter()
+            .filter(|(entry_lang, _, _)| entry_lang == lang)
+            .collect_vec();
+        let mut feeds = vec![(
+            public_dir_path.join(&*lang.id).join("feed.xml"),
+            Feed {
+                url: format!("{base_url}/{}/feed.xml", lang.id).into(),
+                link: format!("{base_url}/{}/", lang.id).into(),
+                lang: lang.locale.clone(),
+                title: site.title.clone(),
+                description: site.description.clone(),
+                entries: lang_entries
+                    .iter()
+                    .map(|(_, _, entry)| entry.clone())
+                    .collect(),
+            },
+        )];
+        for tag in tags.iter().unique_by(|tag| tag.id.clone()) {
+            let info = tag.info(lang.clone());
+            feeds.push((
+                public_dir_path
+                    .join(&*lang.id)
+                    .join("tags")
+                    .join(&*tag.id)
+                    .join("feed.xml"),
+                Feed {
+                    url: format!("{base_url}/{}/tags/{}/feed.xml", lang.id, tag.id).into(),
+                    link: format!("{base_url}/{}/tags/{}", lang.id, tag.id).into(),
+                    lang: lang.locale.clone(),
+                    title: format!("{} | {}", info.title, site.title).into(),
+                    description: info.description,
+                    entries: lang_entries
+                        .iter()
+                        .filter(|(_, meta, _)| meta.has_tag(tag.clone(), tags.clone()))
+                        .map(|(_, _, entry)| entry.clone())
+                        .collect(),
+                },
+            ));
+        }
+        for (path, feed) in feeds {
+            outputs.push(Output {
+                path,
+                source: args.src.to_ipath().join("pages"),
+                // everything the feed is generated from is in the feed itself
+                inputs: Inputs {
+                    source: Some(hash_value(&feed)),
+                    ..inputs(&[], &[], false)
+                },
+                lang: lang.clone(),
+                raw: true,
+                content: Box::new(move || Ok(feed.to_atom())),
+            });
+        }
+    }
+
+    // write the sitemap of all pages, tag pages and "all tags" pages, along with `robots.txt`
+    // pointing to it, which need absolute URLs
+    if args.is_base_url_absolute() {
+        sitemap_pages.push(SitemapPage {
+            variants: languages
+                .iter()
+                .map(|lang| {
+                    let url = format!("{base_url}/{}/tags", lang.id).into();
+                    (lang.locale.clone(), url, None)
+                })
+                .collect(),
+            default_lang: languages.default.locale.clone(),
+        });
+        for tag in tags.iter().unique_by(|tag| tag.id.clone()) {
+            sitemap_pages.push(SitemapPage {
+                variants: languages
+                    .iter()
+                    .map(|lang| {
+                        let url = format!("{base_url}/{}/tags/{}", lang.id, tag.id).into();
+                        let lastmod = feed_entries
+                            .iter()
+                            .filter(|(entry_lang, meta, _)| {
+                                entry_lang == lang && meta.has_tag(tag.clone(), tags.clone())
+                            })
+                            .map(|(_, _, entry)| entry.updated)
+                            .max();
+                        (lang.locale.clone(), url, lastmod)
+                    })
+                    .collect(),
+                default_lang: languages.default.locale.clone(),
+            });
+        }
+        let sitemap_url: IString = format!("{base_url}/sitemap.xml").into();
+        outputs.push(Output {
+            path: public_dir_path.join("sitemap.xml"),
+            source: args.src.to_ipath().join("pages"),
+            inputs: Inputs {
+                source: Some(hash_value(&sitemap_pages)),
+                ..inputs(&[], &[], false)
+            },
+            lang: languages.default.clone(),
+            raw: true,
+            content: Box::new(move || Ok(sitemap(&sitemap_pages))),
+        });
+
+        // `robots.txt` is only read from the root of the host, so with a base URL with a path it is
+        // written outside of the output directory
+        match args.root_dir() {
+            Some(root_dir_path) => outputs.push(Output {
+                path: root_dir_path.join("robots.txt").to_ipath(),
+                source: args.src.to_ipath(),
+                inputs: Inputs {
+                    source: Some(hash_value(&sitemap_url)),
+                    ..inputs(&[], &[], false)
+                },
+                lang: languages.default.clone(),
+                raw: true,
+                content: Box::new(move || Ok(robots_txt(&sitemap_url))),
+            }),
+            None => diagnostics.push(Error::new(
+                ErrorKind::BaseUrl,
+                format!(
+                    "`robots.txt` cannot be written to the root of the host, as the output \
+                     directory `{}` does not end with the path of the base URL `{base_url}`",
+                    public_dir_path.display()
+                ),
+            )),
+        }
+    } else {
+        diagnostics.warn(Error::new(
+            ErrorKind::BaseUrl,
+            format!(
+                "the sitemap and `robots.txt` are not generated, as the base URL `{base_url}` is \
+                 not absolute"
+            ),
+        ));
+    }
+
+    // write the "all tags" pages
+    for lang in languages.iter() {
+        let title = i18ns
+            .format("all_tags".into(), lang.clone(), &[])
+            .map_err(|message| {
+                let kind = match i18ns.contains("all_tags".into()) {
+                    true => ErrorKind::InvalidValue,
+                    false => ErrorKind::MissingI18n,
+                };
+                diagnostics.push(Error::new(kind, format!("{message} for `{}`", lang.id)));
+            })
+            .ok()
+            .map(IString::from);
+        let context = Context {
+            current_lang: lang.clone(),
+            current_tag: None,
+            pages: Default::default(),
+
+            languages: languages.clone(),
+            tags: tags.clone(),
+
+            page: PageMeta {
+                path: "tags".into(),
+                tags: Default::default(),
+                available_in_lang: true,
+                content_lang: lang.id.clone(),
+                languages: languages.iter_ids().cloned().collect(),
+            },
+            title,
+            description: None,
+            toc: Default::default(),
+            backlinks: Default::default(),
+            draft: false,
+            outdated_translation: false,
+            updated: None,
+        };
+        outputs.push(Output {
+            path: public_dir_path.join(&*lang.id).join("tags/index.html"),
+            source: templates.path("tags".into()),
+            inputs: inputs(&[], &[], true),
+            lang: lang.clone(),
+            raw: false,
+            content: Box::new(move || {
+                templates
+                    .render("tags".into(), context.clone(), None)
+                    .and_then(|content| {
+                        templates.render("layout".into(), context.clone(), Some(content))
+                    })
+            }),
+        });
+    }
+
+    // write all tag pages
+    for lang in languages.iter() {
+        for tag_id in tags.iter_ids().cloned() {
+            let tag = tags.get(tag_id.clone()).unwrap();
+            let is_alt_id = tag.id != tag_id;
+            let (uses_templates, content): (bool, Box<dyn Fn() -> _ + Send + Sync>) =
+                match is_alt_id {
+                    false => {
+                        let context = Context {
+                            current_lang: lang.clone(),
+                            current_tag: Some(tag.clone()),
+                            pages: metas
+                                .iter_by_tag(tag.clone(), tags.clone())
+                                .filter_map(|meta| {
+                                    let available_langs =
+                                        pages.get(meta.path.clone())?.keys().cloned().collect();
+                                    Some((meta.clone(), available_langs))
+                                })
+                                .collect(),
+
+                            languages: languages.clone(),
+                            tags: tags.clone(),
+
+                            page: PageMeta {
+                                path: format!("tags/{tag_id}").into(),
+                                tags: Default::default(),
+                                available_in_lang: true,
+                                content_lang: lang.id.clone(),
+                                languages: languages.iter_ids().cloned().collect(),
+                            },
+                            title: Some(tag.title(lang.clone())),
+                            description: Some(tag.description(lang.clone())),
+                            toc: Default::default(),
+                            backlinks: Default::default(),
+                            draft: false,
+                            outdated_translation: false,
+                            updated: None,
+                        };
+                        (
+                            true,
+                            Box::new(move || {
+                                templates
+                                    .render("tag".into(), context.clone(), None)
+                                    .and_then(|content| {
+                                        templates.render(
+                                            "layout".into(),
+                                            context.clone(),
+                                            Some(content),
+                                        )
+                                    })
+                            }),
+                        )
+                    }
+                    // alternative ids redirect to the page of the tag
+                    true => {
+                        let url = format!("{base_url}/{}/tags/{}", lang.id, tag.id);
+                        let redirect: IString =
+                            format!("<meta http-equiv=\"refresh\" content=\"0; url={url}\">")
+                                .into();
+                        (false, Box::new(move || Ok(redirect.clone())))
+                    }
+                };
+            outputs.push(Output {
+                path: public_dir_path
+                    .join(&*lang.id)
+                    .join("tags")
+                    .join(&*tag_id)
+                    .join("index.html"),
+                source: templates.path("tag".into()),
+                inputs: inputs(&[], &[], uses_templates),
+                lang: lang.clone(),
+                raw: false,
+                content,
+            });
+        }
+    }
+
+    // outputs are sorted, so that they are reported in the same order regardless of the order
+    // they are rendered in
+    outputs.sort_by(|a, b| a.path.cmp(&b.path));
+    let count = outputs.len();
+    let outputs = outputs
+        .into_iter()
+        .filter(|output| !cache.is_up_to_date(output.path.clone(), &output.inputs))
+        .collect_vec();
+    let up_to_date = count - outputs.len();
+
+    // render the content into the output file, or discard the output if not writing
+    let results = parallel_map(&outputs, args.jobs(), |output| {
+        let content = (output.content)()?;
+        let io_error = |err: io::Error| {
+            Error::new(ErrorKind::Io, err.to_string()).with_path(output.path.clone())
+        };
+        let mut file: Box<dyn Write> = match write {
+            true => {
+                fs::create_dir_all(output.path.parent().unwrap()).map_err(io_error)?;
+                Box::new(fs::File::create(&output.path).map_err(io_error)?)
+            }
+            false => Box::new(io::sink()),
+        };
+        if output.raw {
+            return file
+                .write_all(content.as_bytes())
+                .map(|()| (Vec::new(), check.map(|_| OutputLinks::default())))
+                .map_err(io_error);
+        }
+        let links = check.map(|_| collect_links(&content, options));
+        my_render(
+            &mut file,
+            content,
+            RenderCtx {
+                lang: output.lang.clone(),
+                base_url: base_url.clone(),
+                i18ns: i18ns.clone(),
+                metas: metas.clone(),
+                pages: pages.clone(),
+                fallback_pages: languages.fallback_pages,
+            },
+            options,
+            &plugins,
+        )
+        .map(|errors| (errors, links))
+        .map_err(io_error)
+    });
+
+    // problems in the content are reported as problems of the `source` file
+    let mut written = 0;
+    let mut output_links = HashMap::new();
+    let mut linking_outputs = Vec::new();
+    for (output, result) in outputs.into_iter().zip(results) {
+        match result {
+            Ok((errors, links)) => {
+                if let Some(links) = links {
+                    output_links.insert(output.path.clone(), links);
+                    if !output.raw {
+                        linking_outputs.push((
+                            output.path.clone(),
+                            output.source.clone(),
+                            output.lang.clone(),
+                        ));
+                    }
+                }
+                if write && args.verbosity() >= 2 {
+                    println!("wrote `{}`", output.path.display());
+                }
+                written += 1;
+                if errors.is_empty() {
+                    cache.insert(output.path.clone(), output.inputs);
+                }
+                diagnostics.extend(
+                    errors
+                        .into_iter()
+                        .map(|err| err.with_path(output.source.clone())),
+                );
+            }
+            Err(err) => diagnostics.push(err),
+        }
+    }
+
+    if let Some(check) = check {
+        // links starting with `/` are resolved against the root of the host
+        let root_dir_path = args
+            .root_dir()
+            .map_or(public_dir_path.clone(), |root| root.to_ipath());
+        let ctx = LinkCtx {
+            root_dir: root_dir_path,
+            out_dir: public_dir_path,
+            base_url: &base_url,
+            pages: &pages,
+            outputs: &output_links,
+            allowlist: check
+                .external
+                .then(|| read_link_allowlist(args.src.to_ipath().join("links.toml"), diagnostics)),
+        };
+        for (path, source, lang) in linking_outputs {
+            diagnostics.extend(
+                check_links(path.clone(), lang, &output_links[&path], &ctx)
+                    .into_iter()
+                    .map(|err| err.with_path(source.clone())),
+            );
+        }
+        return;
+    }
+    // outputs can be missing because of the errors, so those of the previous build are kept
+    let removed = cache.finish(diagnostics.is_empty()).unwrap_or_else(|err| {
+        diagnostics.push(Error::new(ErrorKind::Io, err.to_string()).with_path(public_dir_path));
+        Vec::new()
+    });
+    if args.verbosity() >= 2 {
+        for path in &removed {
+            println!("removed `{}`", path.display());
+        }
+    }
+    if args.verbosity() >= 1 {
+        println!(
+            "wrote {written} file(s), {up_to_date} up to date, removed {}",
+            removed.len()
+        );
+    }
+}
@@ -0,0 +1,1136 @@
+use itertools::Itertools;
+
+use crate::utils::escape_xml;
+
+/// Commands of identifiers (greek letters and other symbols).
+const IDENTIFIERS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("vartheta", "ϑ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("varpi", "ϖ"),
+    ("rho", "ρ"),
+    ("varrho", "ϱ"),
+    ("sigma", "σ"),
+    ("varsigma", "ς"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("infty", "∞"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("hbar", "ℏ"),
+    ("ell", "ℓ"),
+    ("aleph", "ℵ"),
+    ("emptyset", "∅"),
+    ("varnothing", "∅"),
+    ("Re", "ℜ"),
+    ("Im", "ℑ"),
+    ("angle", "∠"),
+    ("triangle", "△"),
+    ("top", "⊤"),
+    ("bot", "⊥"),
+];
+
+/// Commands of identifiers that are upright even when they are a single letter.
+const UPRIGHT_IDENTIFIERS: &[(&str, &str)] = &[
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+];
+
+/// Commands of operators, relations, arrows and delimiters.
+const OPERATORS: &[(&str, &str)] = &[
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("cdot", "⋅"),
+    ("ast", "∗"),
+    ("star", "⋆"),
+    ("circ", "∘"),
+    ("bullet", "∙"),
+    ("oplus", "⊕"),
+    ("ominus", "⊖"),
+    ("otimes", "⊗"),
+    ("oslash", "⊘"),
+    ("odot", "⊙"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("wedge", "∧"),
+    ("land", "∧"),
+    ("vee", "∨"),
+    ("lor", "∨"),
+    ("neg", "¬"),
+    ("lnot", "¬"),
+    ("dagger", "†"),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("leq", "≤"),
+    ("le", "≤"),
+    ("geq", "≥"),
+    ("ge", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("cong", "≅"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("propto", "∝"),
+    ("triangleq", "≜"),
+    ("coloneqq", "≔"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("prec", "≺"),
+    ("succ", "≻"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("ni", "∋"),
+    ("subset", "⊂"),
+    ("supset", "⊃"),
+    ("subseteq", "⊆"),
+    ("supseteq", "⊇"),
+    ("mid", "∣"),
+    ("parallel", "∥"),
+    ("perp", "⊥"),
+    ("vdash", "⊢"),
+    ("models", "⊨"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("nexists", "∄"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("gets", "←"),
+    ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"),
+    ("longrightarrow", "⟶"),
+    ("longleftarrow", "⟵"),
+    ("implies", "⟹"),
+    ("impliedby", "⟸"),
+    ("iff", "⟺"),
+    ("mapsto", "↦"),
+    ("uparrow", "↑"),
+    ("downarrow", "↓"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("vert", "|"),
+    ("lvert", "|"),
+    ("rvert", "|"),
+    ("Vert", "‖"),
+    ("lVert", "‖"),
+    ("rVert", "‖"),
+    ("colon", ":"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("cdots", "⋯"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("prime", "′"),
+];
+
+/// Commands of operators with their scripts under and over them in display formulas.
+const LARGE_OPERATORS: &[(&str, &str)] = &[
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("coprod", "∐"),
+    ("bigcup", "⋃"),
+    ("bigcap", "⋂"),
+    ("bigvee", "⋁"),
+    ("bigwedge", "⋀"),
+    ("bigoplus", "⨁"),
+    ("bigotimes", "⨂"),
+];
+
+/// Commands of integrals, which keep their scripts at the side.
+const INTEGRALS: &[(&str, &str)] = &[("int", "∫"), ("iint", "∬"), ("iiint", "∭"), ("oint", "∮")];
+
+/// Commands of functions, along with whether their scripts go under them in display formulas.
+const FUNCTIONS: &[(&str, &str, bool)] = &[
+    ("sin", "sin", false),
+    ("cos", "cos", false),
+    ("tan", "tan", false),
+    ("cot", "cot", false),
+    ("sec", "sec", false),
+    ("csc", "csc", false),
+    ("arcsin", "arcsin", false),
+    ("arccos", "arccos", false),
+    ("arctan", "arctan", false),
+    ("sinh", "sinh", false),
+    ("cosh", "cosh", false),
+    ("tanh", "tanh", false),
+    ("log", "log", false),
+    ("ln", "ln", false),
+    ("lg", "lg", false),
+    ("exp", "exp", false),
+    ("dim", "dim", false),
+    ("deg", "deg", false),
+    ("ker", "ker", false),
+    ("hom", "hom", false),
+    ("arg", "arg", false),
+    ("lim", "lim", true),
+    ("liminf", "lim inf", true),
+    ("limsup", "lim sup", true),
+    ("min", "min", true),
+    ("max", "max", true),
+    ("argmin", "arg min", true),
+    ("argmax", "arg max", true),
+    ("sup", "sup", true),
+    ("inf", "inf", true),
+    ("det", "det", true),
+    ("gcd", "gcd", true),
+    ("Pr", "Pr", true),
+];
+
+/// Commands of spaces with their widths.
+const SPACES: &[(&str, &str)] = &[
+    (",", "0.1667em"),
+    (":", "0.2222em"),
+    (">", "0.2222em"),
+    (";", "0.2778em"),
+    ("!", "-0.1667em"),
+    (" ", "0.25em"),
+    ("quad", "1em"),
+    ("qquad", "2em"),
+];
+
+/// Commands of accents over their argument, along with whether they stretch over all of it.
+const ACCENTS: &[(&str, &str, bool)] = &[
+    ("hat", "^", false),
+    ("check", "ˇ", false),
+    ("tilde", "~", false),
+    ("acute", "´", false),
+    ("grave", "`", false),
+    ("dot", "˙", false),
+    ("ddot", "¨", false),
+    ("breve", "˘", false),
+    ("bar", "¯", false),
+    ("vec", "→", false),
+    ("widehat", "^", true),
+    ("widetilde", "~", true),
+    ("overline", "‾", true),
+    ("overrightarrow", "→", true),
+    ("overleftarrow", "←", true),
+    ("overbrace", "⏞", true),
+];
+
+/// Commands of accents under their argument.
+const UNDER_ACCENTS: &[(&str, &str)] = &[("underline", "_"), ("underbrace", "⏟")];
+
+/// Commands of sized delimiters with their sizes.
+const DELIMITER_SIZES: &[(&str, &str)] = &[
+    ("big", "1.2em"),
+    ("bigl", "1.2em"),
+    ("bigr", "1.2em"),
+    ("bigm", "1.2em"),
+    ("Big", "1.8em"),
+    ("Bigl", "1.8em"),
+    ("Bigr", "1.8em"),
+    ("Bigm", "1.8em"),
+    ("bigg", "2.4em"),
+    ("biggl", "2.4em"),
+    ("biggr", "2.4em"),
+    ("biggm", "2.4em"),
+    ("Bigg", "3em"),
+    ("Biggl", "3em"),
+    ("Biggr", "3em"),
+    ("Biggm", "3em"),
+];
+
+/// Commands usable as delimiters of `\left`, `\right` and sized delimiters.
+const DELIMITERS: &[&str] = &[
+    "langle",
+    "rangle",
+    "lceil",
+    "rceil",
+    "lfloor",
+    "rfloor",
+    "vert",
+    "lvert",
+    "rvert",
+    "Vert",
+    "lVert",
+    "rVert",
+    "uparrow",
+    "downarrow",
+];
+
+/// Matrix environments with their opening and closing delimiters.
+const MATRICES: &[(&str, &str, &str)] = &[
+    ("matrix", "", ""),
+    ("smallmatrix", "", ""),
+    ("pmatrix", "(", ")"),
+    ("bmatrix", "[", "]"),
+    ("Bmatrix", "{", "}"),
+    ("vmatrix", "|", "|"),
+    ("Vmatrix", "‖", "‖"),
+];
+
+/// Font of letters and digits, set by commands like `\mathbf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variant {
+    Normal,
+    Bold,
+    Italic,
+    BoldItalic,
+    Script,
+    Fraktur,
+    DoubleStruck,
+    SansSerif,
+    Monospace,
+}
+
+const FONTS: &[(&str, Variant)] = &[
+    ("mathrm", Variant::Normal),
+    ("mathup", Variant::Normal),
+    ("mathbf", Variant::Bold),
+    ("mathit", Variant::Italic),
+    ("boldsymbol", Variant::BoldItalic),
+    ("bm", Variant::BoldItalic),
+    ("mathcal", Variant::Script),
+    ("mathscr", Variant::Script),
+    ("mathfrak", Variant::Fraktur),
+    ("mathbb", Variant::DoubleStruck),
+    ("mathsf", Variant::SansSerif),
+    ("mathtt", Variant::Monospace),
+];
+
+const TEXT_FONTS: &[(&str, Option<Variant>)] = &[
+    ("text", None),
+    ("textrm", None),
+    ("textnormal", None),
+    ("mbox", None),
+    ("textbf", Some(Variant::Bold)),
+    ("textit", Some(Variant::Italic)),
+    ("textsf", Some(Variant::SansSerif)),
+    ("texttt", Some(Variant::Monospace)),
+];
+
+impl Variant {
+    /// `c` in the font, as a character of the Mathematical Alphanumeric Symbols block (which,
+    /// unlike the `mathvariant` attribute, is supported by all browsers).
+    fn style(self, c: char) -> char {
+        let holes: &[(char, char)] = match self {
+            Variant::Italic => &[('h', 'ℎ')],
+            Variant::Script => &[
+                ('B', 'ℬ'),
+                ('E', 'ℰ'),
+                ('F', 'ℱ'),
+                ('H', 'ℋ'),
+                ('I', 'ℐ'),
+                ('L', 'ℒ'),
+                ('M', 'ℳ'),
+                ('R', 'ℛ'),
+                ('e', 'ℯ'),
+                ('g', 'ℊ'),
+                ('o', 'ℴ'),
+            ],
+            Variant::Fraktur => &[('C', 'ℭ'), ('H', 'ℌ'), ('I', 'ℑ'), ('R', 'ℜ'), ('Z', 'ℨ')],
+            Variant::DoubleStruck => &[
+                ('C', 'ℂ'),
+                ('H', 'ℍ'),
+                ('N', 'ℕ'),
+                ('P', 'ℙ'),
+                ('Q', 'ℚ'),
+                ('R', 'ℝ'),
+                ('Z', 'ℤ'),
+            ],
+            _ => &[],
+        };
+        if let Some(&(_, styled)) = holes.iter().find(|(plain, _)| *plain == c) {
+            return styled;
+        }
+        // first capital letter, small letter and digit of the font
+        let (capital, small, digit) = match self {
+            Variant::Normal => return c,
+            Variant::Bold => (0x1D400, 0x1D41A, Some(0x1D7CE)),
+            Variant::Italic => (0x1D434, 0x1D44E, None),
+            Variant::BoldItalic => (0x1D468, 0x1D482, None),
+            Variant::Script => (0x1D49C, 0x1D4B6, None),
+            Variant::Fraktur => (0x1D504, 0x1D51E, None),
+            Variant::DoubleStruck => (0x1D538, 0x1D552, Some(0x1D7D8)),
+            Variant::SansSerif => (0x1D5A0, 0x1D5BA, Some(0x1D7E2)),
+            Variant::Monospace => (0x1D670, 0x1D68A, Some(0x1D7F6)),
+        };
+        let code = match c {
+            'A'..='Z' => capital + (c as u32 - 'A' as u32),
+            'a'..='z' => small + (c as u32 - 'a' as u32),
+            '0'..='9' => match digit {
+                Some(digit) => digit + (c as u32 - '0' as u32),
+                None => return c,
+            },
+            _ => return c,
+        };
+        char::from_u32(code).unwrap_or(c)
+    }
+}
+
+/// Render LaTeX math `source` into a MathML `<math>` element, which is a block if `display`.
+/// The source is kept as its annotation.
+///
+/// Only a subset of LaTeX is supported: letters, numbers, operators, `^` and `_` scripts, groups,
+/// common symbol commands (e.g. `\alpha`, `\leq`, `\sum`, `\sin`), `\frac`, `\binom`, `\sqrt`,
+/// accents (e.g. `\hat`, `\overline`), fonts (e.g. `\mathbf`, `\mathbb`), `\text`, spaces,
+/// `\left`/`\middle`/`\right` and sized delimiters, `\overset`, `\underset`, `\not`, `\pmod` and
+/// the `matrix` (and `pmatrix`, ...), `cases`, `aligned` and `gathered` environments.
+pub fn latex_to_mathml(source: &str, display: bool) -> Result<String, String> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        pos: 0,
+        display,
+        variant: None,
+    };
+    let row = parser.row()?;
+    if let Some(c) = parser.peek() {
+        return Err(match parser.command_at() {
+            Some((name, _)) => format!("unexpected `\\{name}`"),
+            None => format!("unexpected `{c}`"),
+        });
+    }
+    Ok(format!(
+        r#"<math{}><semantics>{}<annotation encoding="application/x-tex">{}</annotation></semantics></math>"#,
+        if display { r#" display="block""# } else { "" },
+        wrap_row(row),
+        escape_xml(source)
+    ))
+}
+
+/// Element of a formula, before its scripts.
+struct Atom {
+    mathml: String,
+    /// Scripts go under and over it instead of at its side.
+    limits: bool,
+    /// It is a function (e.g. `sin`), followed by an invisible function application.
+    function: bool,
+}
+
+impl Atom {
+    fn new(mathml: String) -> Atom {
+        Atom {
+            mathml,
+            limits: false,
+            function: false,
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    display: bool,
+    variant: Option<Variant>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let eaten = self.peek() == Some(c);
+        if eaten {
+            self.pos += 1;
+        }
+        eaten
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.whitespace();
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(format!("expected `{c}`")),
+        }
+    }
+
+    fn whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Name of the command at the current position (without `\`), along with the position after
+    /// it.
+    fn command_at(&self) -> Option<(String, usize)> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+        let start = self.pos + 1;
+        let letters = self.chars[start..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .count();
+        let end = match letters {
+            0 => (start + 1).min(self.chars.len()),
+            letters => start + letters,
+        };
+        Some((self.chars[start..end].iter().collect(), end))
+    }
+
+    fn is_command(&self, names: &[&str]) -> bool {
+        self.command_at()
+            .is_some_and(|(name, _)| names.contains(&name.as_str()))
+    }
+
+    /// Parse elements until the end, `}`, `&`, `\\`, `\middle`, `\right` or `\end`, which are not
+    /// consumed.
+    fn row(&mut self) -> Result<Vec<String>, String> {
+        let mut row = Vec::new();
+        loop {
+            self.whitespace();
+            match self.peek() {
+                None | Some('}' | '&') => break,
+                Some('\\') if self.is_command(&["\\", "middle", "right", "end"]) => break,
+                _ => {}
+            }
+            if self.is_command(&["displaystyle", "textstyle"]) {
+                let (name, end) = self.command_at().unwrap();
+                self.pos = end;
+                let rest = self.row()?;
+                row.push(format!(
+                    r#"<mstyle displaystyle="{}">{}</mstyle>"#,
+                    name == "displaystyle",
+                    wrap_row(rest)
+                ));
+                break;
+            }
+            let atom = self.atom(false)?;
+            row.push(self.scripts(atom)?);
+        }
+        Ok(row)
+    }
+
+    /// Parse an element, which is a single character or command if it is an `argument` of a
+    /// command or script (e.g. `1` in `\frac12`).
+    fn atom(&mut self, argument: bool) -> Result<Atom, String> {
+        self.whitespace();
+        let Some(c) = self.peek() else {
+            return Err("unexpected end of the formula".to_owned());
+        };
+        if let Some((name, end)) = self.command_at() {
+            self.pos = end;
+            return self.command(&name);
+        }
+        self.pos += 1;
+        let mathml = match c {
+            '{' => {
+                let row = self.row()?;
+                self.expect('}')?;
+                wrap_row(row)
+            }
+            '^' | '_' if !argument => {
+                // scripts of nothing, like `{}^2`
+                self.pos -= 1;
+                "<mrow></mrow>".to_owned()
+            }
+            '}' | '&' | '^' | '_' | '#' | '%' | '$' => return Err(format!("unexpected `{c}`")),
+            '0'..='9' => {
+                let mut number = c.to_string();
+                if !argument {
+                    while let Some(c) = self.peek() {
+                        let fraction = c == '.'
+                            && !number.contains('.')
+                            && self
+                                .chars
+                                .get(self.pos + 1)
+                                .is_some_and(char::is_ascii_digit);
+                        if !(c.is_ascii_digit() || fraction) {
+                            break;
+                        }
+                        number.push(c);
+                        self.pos += 1;
+                    }
+                }
+                let number: String = number.chars().map(|c| self.style(c)).collect();
+                format!("<mn>{number}</mn>")
+            }
+            '~' => "<mtext>&#xA0;</mtext>".to_owned(),
+            '\'' => "<mo>′</mo>".to_owned(),
+            '-' => "<mo>−</mo>".to_owned(),
+            '*' => "<mo>∗</mo>".to_owned(),
+            c if c.is_alphabetic() => self.identifier(&c.to_string()),
+            c => mo(&c.to_string()),
+        };
+        Ok(Atom::new(mathml))
+    }
+
+    /// Parse the argument of a command or script.
+    fn argument(&mut self) -> Result<String, String> {
+        Ok(self.atom(true)?.mathml)
+    }
+
+    /// Parse the scripts following `atom`, if there are any.
+    fn scripts(&mut self, mut atom: Atom) -> Result<String, String> {
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            self.whitespace();
+            match self.peek() {
+                Some('_') => {
+                    self.pos += 1;
+                    if sub.replace(self.argument()?).is_some() {
+                        return Err("double subscript".to_owned());
+                    }
+                }
+                Some('^') => {
+                    self.pos += 1;
+                    if sup.replace(self.argument()?).is_some() {
+                        return Err("double superscript".to_owned());
+                    }
+                }
+                Some('\'') => {
+                    let mut primes = String::new();
+                    while self.eat('\'') {
+                        primes.push('′');
+                    }
+                    if sup.replace(mo(&primes)).is_some() {
+                        return Err("double superscript".to_owned());
+                    }
+                }
+                Some('\\') if self.is_command(&["limits", "nolimits"]) => {
+                    let (name, end) = self.command_at().unwrap();
+                    self.pos = end;
+                    atom.limits = name == "limits";
+                }
+                _ => break,
+            }
+        }
+        let base = atom.mathml;
+        let (sub_tag, sup_tag, both_tag) = match atom.limits {
+            true => ("munder", "mover", "munderover"),
+            false => ("msub", "msup", "msubsup"),
+        };
+        let mut mathml = match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) => format!("<{sub_tag}>{base}{sub}</{sub_tag}>"),
+            (None, Some(sup)) => format!("<{sup_tag}>{base}{sup}</{sup_tag}>"),
+            (Some(sub), Some(sup)) => format!("<{both_tag}>{base}{sub}{sup}</{both_tag}>"),
+        };
+        if atom.function {
+            mathml.push_str("<mo>&#x2061;</mo>");
+        }
+        Ok(mathml)
+    }
+
+    /// Parse the arguments of the command `name`, which is already consumed.
+    fn command(&mut self, name: &str) -> Result<Atom, String> {
+        if name.is_empty() {
+            return Err("expected a command after `\\`".to_owned());
+        }
+        let find = |table: &[(&str, &'static str)]| {
+            table
+                .iter()
+                .find(|(command, _)| *command == name)
+                .map(|&(_, value)| value)
+        };
+        if let Some(symbol) = find(IDENTIFIERS) {
+            return Ok(Atom::new(self.identifier(symbol)));
+        }
+        if let Some(symbol) = find(UPRIGHT_IDENTIFIERS) {
+            return Ok(Atom::new(format!(
+                r#"<mi mathvariant="normal">{symbol}</mi>"#
+            )));
+        }
+        if let Some(symbol) = find(OPERATORS).or_else(|| find(INTEGRALS)) {
+            return Ok(Atom::new(mo(symbol)));
+        }
+        if let Some(symbol) = find(LARGE_OPERATORS) {
+            return Ok(Atom {
+                limits: self.display,
+                ..Atom::new(mo(symbol))
+            });
+        }
+        if let Some(&(_, text, limits)) = FUNCTIONS.iter().find(|(command, ..)| *command == name) {
+            return Ok(Atom {
+                mathml: format!("<mi>{text}</mi>"),
+                limits: limits && self.display,
+                function: true,
+            });
+        }
+        if let Some(width) = find(SPACES) {
+            return Ok(Atom::new(format!(r#"<mspace width="{width}"/>"#)));
+        }
+        if let Some(&(_, accent, stretchy)) = ACCENTS.iter().find(|(command, ..)| *command == name)
+        {
+            let base = self.argument()?;
+            return Ok(Atom {
+                limits: name == "overbrace",
+                ..Atom::new(format!(
+                    r#"<mover accent="true">{base}<mo stretchy="{stretchy}">{accent}</mo></mover>"#
+                ))
+            });
+        }
+        if let Some(accent) = find(UNDER_ACCENTS) {
+            let base = self.argument()?;
+            return Ok(Atom {
+                limits: name == "underbrace",
+                ..Atom::new(format!(
+                    r#"<munder accentunder="true">{base}<mo stretchy="true">{accent}</mo></munder>"#
+                ))
+            });
+        }
+        if let Some(size) = find(DELIMITER_SIZES) {
+            let delimiter = self.delimiter()?;
+            return Ok(Atom::new(format!(
+                r#"<mo minsize="{size}" maxsize="{size}">{delimiter}</mo>"#
+            )));
+        }
+        if let Some(&(_, variant)) = FONTS.iter().find(|(command, _)| *command == name) {
+            let previous = self.variant.replace(variant);
+            let argument = self.argument();
+            self.variant = previous;
+            return Ok(Atom::new(argument?));
+        }
+        if let Some(&(_, variant)) = TEXT_FONTS.iter().find(|(command, _)| *command == name) {
+            let text = self.text()?;
+            let text = match variant {
+                Some(variant) => text.chars().map(|c| variant.style(c)).collect(),
+                None => text,
+            };
+            return Ok(Atom::new(format!(
+                "<mtext>{}</mtext>",
+                escape_xml(&text).replace(' ', "&#xA0;")
+            )));
+        }
+        let mathml = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.argument()?;
+                let denominator = self.argument()?;
+                let fraction = format!("<mfrac>{numerator}{denominator}</mfrac>");
+                match name {
+                    "dfrac" | "cfrac" => {
+                        format!(r#"<mstyle displaystyle="true">{fraction}</mstyle>"#)
+                    }
+                    "tfrac" => format!(r#"<mstyle displaystyle="false">{fraction}</mstyle>"#),
+                    _ => fraction,
+                }
+            }
+            "binom" => {
+                let top = self.argument()?;
+                let bottom = self.argument()?;
+                format!(
+                    r#"<mrow><mo>(</mo><mfrac linethickness="0">{top}{bottom}</mfrac><mo>)</mo></mrow>"#
+                )
+            }
+            "sqrt" => {
+                self.whitespace();
+                match self.eat('[') {
+                    true => {
+                        let mut index = Vec::new();
+                        loop {
+                            self.whitespace();
+                            if self.eat(']') {
+                                break;
+                            }
+                            let atom = self.atom(false)?;
+                            index.push(self.scripts(atom)?);
+                        }
+                        let radicand = self.argument()?;
+                        format!("<mroot>{radicand}{}</mroot>", wrap_row(index))
+                    }
+                    false => format!("<msqrt>{}</msqrt>", self.argument()?),
+                }
+            }
+            "operatorname" => {
+                return Ok(Atom {
+                    function: true,
+                    ..Atom::new(format!("<mi>{}</mi>", escape_xml(&self.text()?)))
+                })
+            }
+            "overset" | "stackrel" => {
+                let over = self.argument()?;
+                let base = self.argument()?;
+                format!("<mover>{base}{over}</mover>")
+            }
+            "underset" => {
+                let under = self.argument()?;
+                let base = self.argument()?;
+                format!("<munder>{base}{under}</munder>")
+            }
+            "not" => {
+                let relation = self.argument()?;
+                match relation
+                    .strip_prefix("<mo>")
+                    .and_then(|relation| relation.strip_suffix("</mo>"))
+                {
+                    Some(relation) => format!("<mo>{relation}\u{338}</mo>"),
+                    None => return Err("`\\not` should be followed by a relation".to_owned()),
+                }
+            }
+            "pmod" => format!(
+                r#"<mrow><mspace width="0.5em"/><mo>(</mo><mi>mod</mi><mspace width="0.3333em"/>{}<mo>)</mo></mrow>"#,
+                self.argument()?
+            ),
+            "bmod" | "mod" => "<mo>mod</mo>".to_owned(),
+            "{" | "}" => mo(name),
+            "|" => mo("‖"),
+            "#" | "$" | "%" | "&" | "_" => format!("<mi>{}</mi>", escape_xml(name)),
+            "left" => {
+                let mut row = vec![fence(&self.delimiter()?, true)];
+                loop {
+                    row.extend(self.row()?);
+                    match self.command_at() {
+                        Some((name, end)) if name == "middle" || name == "right" => {
+                            self.pos = end;
+                            let delimiter = self.delimiter()?;
+                            if name == "right" {
+                                row.push(fence(&delimiter, true));
+                                break;
+                            }
+                            row.push(fence(&delimiter, false));
+                        }
+                        _ => return Err("`\\left` without `\\right`".to_owned()),
+                    }
+                }
+                format!("<mrow>{}</mrow>", row.concat())
+            }
+            "begin" => self.environment()?,
+            "middle" | "right" => return Err(format!("`\\{name}` without `\\left`")),
+            "end" => return Err("`\\end` without `\\begin`".to_owned()),
+            "\\" => return Err("`\\\\` outside of an environment".to_owned()),
+            "limits" | "nolimits" => return Err(format!("`\\{name}` should follow an operator")),
+            _ => return Err(format!("unknown command `\\{name}`")),
+        };
+        Ok(Atom::new(mathml))
+    }
+
+    /// Parse a delimiter of `\left`, `\right` or a sized delimiter. `.` is an empty one.
+    fn delimiter(&mut self) -> Result<String, String> {
+        self.whitespace();
+        if let Some((name, end)) = self.command_at() {
+            let delimiter = match name.as_str() {
+                "{" | "}" => name.clone(),
+                "|" => "‖".to_owned(),
+                name if DELIMITERS.contains(&name) => OPERATORS
+                    .iter()
+                    .find(|(command, _)| *command == name)
+                    .map(|(_, symbol)| symbol.to_string())
+                    .unwrap_or_default(),
+                _ => return Err(format!("expected a delimiter, found `\\{name}`")),
+            };
+            self.pos = end;
+            return Ok(delimiter);
+        }
+        match self.peek() {
+            Some(c @ ('(' | ')' | '[' | ']' | '|' | '/' | '<' | '>' | '.')) => {
+                self.pos += 1;
+                Ok(match c {
+                    '.' => String::new(),
+                    '<' => "⟨".to_owned(),
+                    '>' => "⟩".to_owned(),
+                    c => c.to_string(),
+                })
+            }
+            _ => Err("expected a delimiter".to_owned()),
+        }
+    }
+
+    /// Parse the text in braces of a command like `\text`.
+    fn text(&mut self) -> Result<String, String> {
+        self.expect('{')?;
+        let mut text = String::new();
+        let mut depth = 0;
+        loop {
+            match self.chars.get(self.pos) {
+                None => return Err("unterminated text".to_owned()),
+                Some('}') if depth == 0 => break,
+                Some('\\') => {
+                    match self.chars.get(self.pos + 1) {
+                        Some(&c @ ('{' | '}' | '\\' | '$' | '%' | '&' | '_' | '#')) => text.push(c),
+                        _ => return Err("only escaped characters are supported in text".to_owned()),
+                    }
+                    self.pos += 1;
+                }
+                Some(&c) => match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => text.push(c),
+                },
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        Ok(text)
+    }
+
+    /// Parse an environment after its `\begin`.
+    fn environment(&mut self) -> Result<String, String> {
+        let name = self.text()?;
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        let mut cells = Vec::new();
+        loop {
+            cells.push(wrap_row(self.row()?));
+            if self.eat('&') {
+                continue;
+            }
+            match self.command_at() {
+                Some((command, end)) if command == "\\" => {
+                    self.pos = end;
+                    rows.push(std::mem::take(&mut cells));
+                }
+                Some((command, end)) if command == "end" => {
+                    self.pos = end;
+                    let end_name = self.text()?;
+                    if end_name != name {
+                        return Err(format!(
+                            "`\\begin{{{name}}}` is ended by `\\end{{{end_name}}}`"
+                        ));
+                    }
+                    rows.push(cells);
+                    break;
+                }
+                _ => return Err(format!("`\\begin{{{name}}}` without `\\end{{{name}}}`")),
+            }
+        }
+        // a `\\` at the end of the last row
+        if rows.len() > 1 && rows.last().is_some_and(|row| row == &["<mrow></mrow>"]) {
+            rows.pop();
+        }
+        let (open, close, align): (&str, &str, fn(usize) -> &'static str) = match name.as_str() {
+            "cases" => ("{", "", |_| "left"),
+            "aligned" | "align" | "align*" | "split" => ("", "", |column| match column % 2 {
+                0 => "right",
+                _ => "left",
+            }),
+            "gathered" | "gather" | "gather*" => ("", "", |_| "center"),
+            name => match MATRICES.iter().find(|(matrix, ..)| *matrix == name) {
+                Some(&(_, open, close)) => (open, close, |_| "center"),
+                None => return Err(format!("unknown environment `{name}`")),
+            },
+        };
+        let table = rows
+            .iter()
+            .map(|row| {
+                let cells = row
+                    .iter()
+                    .enumerate()
+                    .map(|(column, cell)| {
+                        format!(r#"<mtd style="text-align: {}">{cell}</mtd>"#, align(column))
+                    })
+                    .join("");
+                format!("<mtr>{cells}</mtr>")
+            })
+            .join("");
+        let mut mathml = format!("<mtable>{table}</mtable>");
+        if !open.is_empty() || !close.is_empty() {
+            mathml = format!(
+                "<mrow>{}{mathml}{}</mrow>",
+                fence(open, true),
+                fence(close, true)
+            );
+        }
+        Ok(mathml)
+    }
+
+    /// `text` as an identifier in the current font.
+    fn identifier(&self, text: &str) -> String {
+        match self.variant {
+            Some(Variant::Normal) => {
+                format!(r#"<mi mathvariant="normal">{}</mi>"#, escape_xml(text))
+            }
+            _ => {
+                let text: String = text.chars().map(|c| self.style(c)).collect();
+                format!("<mi>{}</mi>", escape_xml(&text))
+            }
+        }
+    }
+
+    fn style(&self, c: char) -> char {
+        match self.variant {
+            Some(variant) => variant.style(c),
+            None => c,
+        }
+    }
+}
+
+fn mo(text: &str) -> String {
+    format!("<mo>{}</mo>", escape_xml(text))
+}
+
+/// Stretchy delimiter, which is `fence` if it opens or closes a group rather than separates it.
+fn fence(delimiter: &str, fence: bool) -> String {
+    match delimiter {
+        "" => String::new(),
+        delimiter => format!(
+            r#"<mo fence="{fence}" stretchy="true">{}</mo>"#,
+            escape_xml(delimiter)
+        ),
+    }
+}
+
+/// Single element of a row, or the `<mrow>` of its elements.
+fn wrap_row(row: Vec<String>) -> String {
+    match <[String; 1]>::try_from(row) {
+        Ok([element]) => element,
+        Err(row) => format!("<mrow>{}</mrow>", row.concat()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MathML of the inline formula `source`, without the `<math>` element and the annotation.
+    fn render(source: &str) -> String {
+        let mathml = latex_to_mathml(source, false).unwrap();
+        let start = "<math><semantics>".len();
+        let end = mathml.find("<annotation").unwrap();
+        mathml[start..end].to_owned()
+    }
+
+    fn error(source: &str) -> String {
+        latex_to_mathml(source, false).unwrap_err()
+    }
+
+    #[test]
+    fn element() {
+        assert_eq!(
+            latex_to_mathml("x < 1", true).unwrap(),
+            "<math display=\"block\"><semantics><mrow><mi>x</mi><mo>&lt;</mo><mn>1</mn></mrow>\
+             <annotation encoding=\"application/x-tex\">x &lt; 1</annotation></semantics></math>"
+        );
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(
+            render(r"\frac{1}{2}"),
+            "<mfrac><mn>1</mn><mn>2</mn></mfrac>"
+        );
+        assert_eq!(render(r"\frac12"), "<mfrac><mn>1</mn><mn>2</mn></mfrac>");
+        assert_eq!(
+            render(r"\dfrac{a}{b + c}"),
+            "<mstyle displaystyle=\"true\"><mfrac><mi>a</mi>\
+             <mrow><mi>b</mi><mo>+</mo><mi>c</mi></mrow></mfrac></mstyle>"
+        );
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(render("x^2"), "<msup><mi>x</mi><mn>2</mn></msup>");
+        assert_eq!(render("a_i"), "<msub><mi>a</mi><mi>i</mi></msub>");
+        assert_eq!(
+            render("x_{i+1}^2"),
+            "<msubsup><mi>x</mi><mrow><mi>i</mi><mo>+</mo><mn>1</mn></mrow><mn>2</mn></msubsup>"
+        );
+        assert_eq!(render("f'"), "<msup><mi>f</mi><mo>′</mo></msup>");
+        assert_eq!(
+            render(r"\sum_{i=1}^n"),
+            "<msubsup><mo>∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></msubsup>"
+        );
+        assert_eq!(
+            latex_to_mathml(r"\sum_i", true).unwrap(),
+            "<math display=\"block\"><semantics><munder><mo>∑</mo><mi>i</mi></munder>\
+             <annotation encoding=\"application/x-tex\">\\sum_i</annotation></semantics></math>"
+        );
+    }
+
+    #[test]
+    fn roots() {
+        assert_eq!(render(r"\sqrt{2}"), "<msqrt><mn>2</mn></msqrt>");
+        assert_eq!(
+            render(r"\sqrt[3]{x}"),
+            "<mroot><mi>x</mi><mn>3</mn></mroot>"
+        );
+    }
+
+    #[test]
+    fn environments() {
+        assert_eq!(
+            render(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+            "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mtable>\
+             <mtr><mtd style=\"text-align: center\"><mi>a</mi></mtd>\
+             <mtd style=\"text-align: center\"><mi>b</mi></mtd></mtr>\
+             <mtr><mtd style=\"text-align: center\"><mi>c</mi></mtd>\
+             <mtd style=\"text-align: center\"><mi>d</mi></mtd></mtr>\
+             </mtable><mo fence=\"true\" stretchy=\"true\">)</mo></mrow>"
+        );
+        assert_eq!(
+            render(r"\begin{cases} 1 & x > 0 \\ 0 & x \leq 0 \\ \end{cases}"),
+            "<mrow><mo fence=\"true\" stretchy=\"true\">{</mo><mtable>\
+             <mtr><mtd style=\"text-align: left\"><mn>1</mn></mtd>\
+             <mtd style=\"text-align: left\"><mrow><mi>x</mi><mo>&gt;</mo><mn>0</mn></mrow></mtd>\
+             </mtr><mtr><mtd style=\"text-align: left\"><mn>0</mn></mtd>\
+             <mtd style=\"text-align: left\"><mrow><mi>x</mi><mo>≤</mo><mn>0</mn></mrow></mtd>\
+             </mtr></mtable></mrow>"
+        );
+    }
+
+    #[test]
+    fn escaped_characters() {
+        assert_eq!(render(r"\$5"), "<mrow><mi>$</mi><mn>5</mn></mrow>");
+        assert_eq!(
+            render(r"\{\%\}"),
+            "<mrow><mo>{</mo><mi>%</mi><mo>}</mo></mrow>"
+        );
+        assert_eq!(
+            render(r"\text{\$ and \{\}}"),
+            "<mtext>$&#xA0;and&#xA0;{}</mtext>"
+        );
+        assert_eq!(error("$"), "unexpected `$`");
+    }
+
+    #[test]
+    fn unbalanced_braces() {
+        assert_eq!(error("{x"), "expected `}`");
+        assert_eq!(error("x}"), "unexpected `}`");
+        assert_eq!(error(r"\frac{1}{2"), "expected `}`");
+        assert_eq!(error(r"\text{x"), "unterminated text");
+        assert_eq!(error(r"\left( x"), "`\\left` without `\\right`");
+        assert_eq!(error(r"x \right)"), "unexpected `\\right`");
+    }
+
+    #[test]
+    fn unknown_commands() {
+        assert_eq!(error(r"\foo"), "unknown command `\\foo`");
+        assert_eq!(error(r"x + \foo{y}"), "unknown command `\\foo`");
+        assert_eq!(
+            error(r"\begin{foo} x \end{foo}"),
+            "unknown environment `foo`"
+        );
+        assert_eq!(
+            error(r"\begin{matrix} x \end{cases}"),
+            "`\\begin{matrix}` is ended by `\\end{cases}`"
+        );
+        assert_eq!(error(r"\end{matrix}"), "unexpected `\\end`");
+    }
+
+    #[test]
+    fn invalid_scripts() {
+        assert_eq!(error("x^1^2"), "double superscript");
+        assert_eq!(error("x_1_2"), "double subscript");
+        assert_eq!(error("x^"), "unexpected end of the formula");
+    }
+}
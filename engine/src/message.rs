@@ -0,0 +1,478 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use implicit_clone::sync::IString;
+
+/// Plural categories of CLDR, in the order they are usually listed.
+pub const PLURAL_CATEGORIES: [&str; 6] = ["zero", "one", "two", "few", "many", "other"];
+
+/// Value of a named placeholder of a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Number(i64),
+    Text(IString),
+}
+
+impl Display for Arg {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Arg::Number(number) => write!(f, "{number}"),
+            Arg::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+/// Message of an i18n, in a subset of the ICU MessageFormat syntax:
+///
+/// - `{name}` is replaced by the value of the `name` placeholder.
+/// - `{name, plural, =0 {none} one {# page} other {# pages}}` chooses a variant by the number of
+///   the `name` placeholder, either exactly (`=0`) or by its CLDR plural category in the language.
+///   `#` in the variant is replaced by the number.
+/// - `'` quotes special characters (e.g. `'{'` is `{`), `''` is `'`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Arg(String),
+    Plural {
+        arg: String,
+        variants: Vec<(Selector, Message)>,
+    },
+    /// Number of the innermost plural.
+    Number,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Exact(i64),
+    Category(String),
+}
+
+impl Message {
+    /// Parse a message. Errors are messages with the byte offset they are located at.
+    pub fn parse(source: &str) -> Result<Message, (usize, String)> {
+        let mut parser = Parser {
+            source,
+            chars: source.char_indices().peekable(),
+        };
+        let message = parser.message(false)?;
+        match parser.chars.next() {
+            Some((offset, _)) => Err((offset, "unmatched `}`".to_owned())),
+            None => Ok(message),
+        }
+    }
+
+    /// Names of all placeholders used in the message.
+    pub fn args(&self) -> Vec<&str> {
+        let mut args = Vec::new();
+        for part in &self.parts {
+            match part {
+                Part::Arg(arg) => args.push(arg.as_str()),
+                Part::Plural { arg, variants } => {
+                    args.push(arg.as_str());
+                    args.extend(variants.iter().flat_map(|(_, message)| message.args()));
+                }
+                Part::Text(_) | Part::Number => {}
+            }
+        }
+        args
+    }
+
+    /// Replace the placeholders with `args`, choosing plural variants by the rules of the language
+    /// `lang_id`.
+    pub fn format(&self, lang_id: &str, args: &[(&str, Arg)]) -> Result<String, String> {
+        let mut output = String::new();
+        self.format_into(&mut output, lang_id, args, None)?;
+        Ok(output)
+    }
+
+    fn format_into(
+        &self,
+        output: &mut String,
+        lang_id: &str,
+        args: &[(&str, Arg)],
+        number: Option<i64>,
+    ) -> Result<(), String> {
+        let get = |name: &str| {
+            args.iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| format!("missing value of `{name}`"))
+        };
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => output.push_str(text),
+                Part::Arg(name) => output.push_str(&get(name)?.to_string()),
+                Part::Number => match number {
+                    Some(number) => output.push_str(&number.to_string()),
+                    None => output.push('#'),
+                },
+                Part::Plural { arg, variants } => {
+                    let Arg::Number(number) = get(arg)? else {
+                        return Err(format!("value of `{arg}` should be a number"));
+                    };
+                    let category = plural_category(lang_id, *number);
+                    let (_, message) = variants
+                        .iter()
+                        .find(|(selector, _)| *selector == Selector::Exact(*number))
+                        .or_else(|| {
+                            variants.iter().find(|(selector, _)| {
+                                *selector == Selector::Category(category.to_owned())
+                            })
+                        })
+                        .or_else(|| {
+                            variants.iter().find(|(selector, _)| {
+                                *selector == Selector::Category("other".to_owned())
+                            })
+                        })
+                        .expect("plurals should have the `other` variant");
+                    message.format_into(output, lang_id, args, Some(*number))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    /// Parse until the end or an unmatched `}`, which is not consumed.
+    fn message(&mut self, in_plural: bool) -> Result<Message, (usize, String)> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        while let Some(&(offset, c)) = self.chars.peek() {
+            match c {
+                '}' => break,
+                '{' => {
+                    self.chars.next();
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(self.placeholder(offset)?);
+                }
+                '#' if in_plural => {
+                    self.chars.next();
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Number);
+                }
+                '\'' => {
+                    self.chars.next();
+                    match self.chars.peek() {
+                        Some((_, '\'')) => {
+                            self.chars.next();
+                            text.push('\'');
+                        }
+                        Some((_, '{' | '}' | '#')) => loop {
+                            match self.chars.next() {
+                                Some((_, '\'')) if self.eat('\'') => text.push('\''),
+                                Some((_, '\'')) => break,
+                                Some((_, c)) => text.push(c),
+                                None => return Err((offset, "unterminated quote".to_owned())),
+                            }
+                        },
+                        _ => text.push('\''),
+                    }
+                }
+                c => {
+                    self.chars.next();
+                    text.push(c);
+                }
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Message { parts })
+    }
+
+    /// Parse a placeholder after its `{` at `start`.
+    fn placeholder(&mut self, start: usize) -> Result<Part, (usize, String)> {
+        let arg = self.word();
+        if arg.is_empty() {
+            return Err((start, "expected a placeholder name after `{`".to_owned()));
+        }
+        self.whitespace();
+        if self.eat('}') {
+            return Ok(Part::Arg(arg));
+        }
+        if !self.eat(',') {
+            return Err((
+                self.offset(),
+                "expected `}` or `,` after the name".to_owned(),
+            ));
+        }
+        self.whitespace();
+        let kind_offset = self.offset();
+        match self.word().as_str() {
+            "plural" => {}
+            kind => {
+                return Err((
+                    kind_offset,
+                    format!("unknown placeholder kind `{kind}`, expected `plural`"),
+                ))
+            }
+        }
+        self.whitespace();
+        if !self.eat(',') {
+            return Err((self.offset(), "expected `,` after `plural`".to_owned()));
+        }
+        let mut variants = Vec::new();
+        loop {
+            self.whitespace();
+            if self.eat('}') {
+                break;
+            }
+            let selector_offset = self.offset();
+            let selector =
+                match self.eat('=') {
+                    true => Selector::Exact(self.word().parse().map_err(|_| {
+                        (selector_offset, "expected a number after `=`".to_owned())
+                    })?),
+                    false => {
+                        let category = self.word();
+                        if !PLURAL_CATEGORIES.contains(&category.as_str()) {
+                            return Err((
+                                selector_offset,
+                                format!(
+                                    "unknown plural category `{category}`, expected one of {}",
+                                    PLURAL_CATEGORIES.join(", ")
+                                ),
+                            ));
+                        }
+                        Selector::Category(category)
+                    }
+                };
+            self.whitespace();
+            if !self.eat('{') {
+                return Err((self.offset(), "expected `{` before the variant".to_owned()));
+            }
+            let message = self.message(true)?;
+            if !self.eat('}') {
+                return Err((self.offset(), "unterminated variant".to_owned()));
+            }
+            variants.push((selector, message));
+        }
+        if !variants
+            .iter()
+            .any(|(selector, _)| *selector == Selector::Category("other".to_owned()))
+        {
+            return Err((start, format!("plural of `{arg}` is missing `other`")));
+        }
+        Ok(Part::Plural { arg, variants })
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map_or(self.source.len(), |(offset, _)| *offset)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.chars.next_if(|(_, next)| *next == c).is_some()
+    }
+
+    fn whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn word(&mut self) -> String {
+        self.whitespace();
+        let mut word = String::new();
+        while let Some((_, c)) = self
+            .chars
+            .next_if(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '-')
+        {
+            word.push(c);
+        }
+        word
+    }
+}
+
+/// CLDR plural category of the integer `n` in the language `lang_id` (e.g. `ru` or `pt-BR`).
+/// Languages without known rules use the English ones.
+pub fn plural_category(lang_id: &str, n: i64) -> &'static str {
+    let language = lang_id
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let n = n.unsigned_abs();
+    let (n10, n100) = (n % 10, n % 100);
+    match language.as_str() {
+        "ja" | "zh" | "ko" | "th" | "vi" | "id" | "ms" | "lo" | "my" | "km" => "other",
+        "fr" | "pt" | "hi" | "bn" | "fa" => match n {
+            0 | 1 => "one",
+            _ => "other",
+        },
+        "ru" | "uk" | "be" => match (n10, n100) {
+            (1, _) if n100 != 11 => "one",
+            (2..=4, _) if !(12..=14).contains(&n100) => "few",
+            _ => "many",
+        },
+        "pl" => match (n, n10, n100) {
+            (1, _, _) => "one",
+            (_, 2..=4, _) if !(12..=14).contains(&n100) => "few",
+            _ => "many",
+        },
+        "cs" | "sk" => match n {
+            1 => "one",
+            2..=4 => "few",
+            _ => "other",
+        },
+        "lt" => match (n10, n100) {
+            (_, 11..=19) => "other",
+            (1, _) => "one",
+            (2..=9, _) => "few",
+            _ => "other",
+        },
+        "lv" => match (n10, n100) {
+            (0, _) | (_, 11..=19) => "zero",
+            (1, _) => "one",
+            _ => "other",
+        },
+        "ar" => match (n, n100) {
+            (0, _) => "zero",
+            (1, _) => "one",
+            (2, _) => "two",
+            (_, 3..=10) => "few",
+            (_, 11..=99) => "many",
+            _ => "other",
+        },
+        "he" => match n {
+            1 => "one",
+            2 => "two",
+            _ => "other",
+        },
+        _ => match n {
+            1 => "one",
+            _ => "other",
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str, lang_id: &str, args: &[(&str, Arg)]) -> String {
+        Message::parse(source)
+            .unwrap()
+            .format(lang_id, args)
+            .unwrap()
+    }
+
+    fn count(source: &str, lang_id: &str, count: i64) -> String {
+        format(source, lang_id, &[("count", Arg::Number(count))])
+    }
+
+    #[test]
+    fn placeholders() {
+        let args = [("name", Arg::Text("Ann".into())), ("n", Arg::Number(3))];
+        assert_eq!(format("{name} has {n}", "en", &args), "Ann has 3");
+        assert_eq!(format("{ name }", "en", &args), "Ann");
+        assert_eq!(
+            Message::parse("{name}").unwrap().format("en", &[]),
+            Err("missing value of `name`".to_owned())
+        );
+    }
+
+    #[test]
+    fn plurals_en() {
+        let source = "{count, plural, =0 {no pages} one {# page} other {# pages}}";
+        assert_eq!(count(source, "en", 0), "no pages");
+        assert_eq!(count(source, "en", 1), "1 page");
+        assert_eq!(count(source, "en", 2), "2 pages");
+        assert_eq!(count(source, "en", 21), "21 pages");
+    }
+
+    #[test]
+    fn plurals_ru() {
+        let source = "{count, plural, one {# страница} few {# страницы} many {# страниц} other {# \
+                      страницы}}";
+        assert_eq!(count(source, "ru", 1), "1 страница");
+        assert_eq!(count(source, "ru", 2), "2 страницы");
+        assert_eq!(count(source, "ru", 5), "5 страниц");
+        assert_eq!(count(source, "ru", 11), "11 страниц");
+        assert_eq!(count(source, "ru", 12), "12 страниц");
+        assert_eq!(count(source, "ru", 21), "21 страница");
+        assert_eq!(count(source, "ru", 22), "22 страницы");
+        assert_eq!(count(source, "ru", 111), "111 страниц");
+    }
+
+    #[test]
+    fn plural_of_text() {
+        let message = Message::parse("{count, plural, other {#}}").unwrap();
+        assert_eq!(
+            message.format("en", &[("count", Arg::Text("a".into()))]),
+            Err("value of `count` should be a number".to_owned())
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        let args = [("name", Arg::Text("Ann".into()))];
+        assert_eq!(
+            format("'{'name'}' is ''{name}''", "en", &args),
+            "{name} is 'Ann'"
+        );
+        assert_eq!(format("it's {name}'s", "en", &args), "it's Ann's");
+        assert_eq!(count("{count, plural, other {'#'#}}", "en", 1), "#1");
+        assert_eq!(format("# is not special", "en", &[]), "# is not special");
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| Message::parse(source).unwrap_err();
+        assert_eq!(
+            error("a {}"),
+            (2, "expected a placeholder name after `{`".to_owned())
+        );
+        assert_eq!(error("a }"), (2, "unmatched `}`".to_owned()));
+        assert_eq!(error("ы }"), (3, "unmatched `}`".to_owned()));
+        assert_eq!(
+            error("{n x}"),
+            (3, "expected `}` or `,` after the name".to_owned())
+        );
+        assert_eq!(
+            error("{n, select, other {a}}"),
+            (
+                4,
+                "unknown placeholder kind `select`, expected `plural`".to_owned()
+            )
+        );
+        assert_eq!(
+            error("{n, plural, =x {a} other {b}}"),
+            (12, "expected a number after `=`".to_owned())
+        );
+        assert_eq!(
+            error("{n, plural, lots {a} other {b}}"),
+            (
+                12,
+                "unknown plural category `lots`, expected one of zero, one, two, few, many, other"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            error("{n, plural, other a}"),
+            (18, "expected `{` before the variant".to_owned())
+        );
+        assert_eq!(
+            error("{n, plural, one {a}}"),
+            (0, "plural of `n` is missing `other`".to_owned())
+        );
+        assert_eq!(error("a '{b"), (2, "unterminated quote".to_owned()));
+    }
+}
@@ -1,195 +1,531 @@
-use std::fs;
-use std::ops::Index;
-
-use implicit_clone::sync::IArray;
-use implicit_clone::sync::IMap;
-use implicit_clone::sync::IString;
-use implicit_clone::ImplicitClone;
-use itertools::Itertools;
-
-use crate::language::Language;
-use crate::language::LanguageStore;
-use crate::sync::path::IPath;
-use crate::tag::Tag;
-use crate::tag::TagStore;
-use crate::utils::all_path_ids;
-use crate::utils::all_possible_indices;
-use crate::utils::GetRef;
-use crate::utils::Info;
-
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Meta {
-    pub path: IPath,
-    pub tags: IArray<Tag>,
-    pub default_info: Info<IString>,
-    pub infos: IMap<Language, Info<Option<IString>>>,
-}
-
-impl Meta {
-    pub fn info(&self, lang: Language) -> Info<IString> {
-        self.infos
-            .get(&lang)
-            .map(|info| Info {
-                title: info
-                    .title
-                    .unwrap_or_else(|| self.default_info.title.clone()),
-                description: info
-                    .description
-                    .unwrap_or_else(|| self.default_info.description.clone()),
-            })
-            .unwrap_or_else(|| self.default_info.clone())
-    }
-
-    pub fn title(&self, lang: Language) -> IString {
-        self.infos
-            .get(&lang)
-            .and_then(|info| info.title)
-            .unwrap_or_else(|| self.default_info.title.clone())
-    }
-
-    pub fn description(&self, lang: Language) -> IString {
-        self.infos
-            .get(&lang)
-            .and_then(|info| info.description)
-            .unwrap_or_else(|| self.default_info.description.clone())
-    }
-}
-
-impl ImplicitClone for Meta {}
-
-#[derive(Debug, Clone)]
-pub struct MetaStore {
-    pub metas: IMap<IPath, Meta>,
-}
-
-impl MetaStore {
-    pub fn get(&self, path: IPath) -> Option<Meta> {
-        self.metas.get(&path)
-    }
-
-    pub fn iter(&self) -> impl Iterator<Item = &Meta> + '_ {
-        self.metas.values()
-    }
-
-    pub fn iter_ids(&self) -> impl Iterator<Item = &IPath> + '_ {
-        self.metas.keys()
-    }
-
-    pub fn iter_by_tag(&self, tag: Tag) -> impl Iterator<Item = &Meta> + '_ {
-        self.iter().filter(move |meta| meta.tags.contains(&tag))
-    }
-
-    pub fn title(&self, path: IPath, lang: Language) -> Option<IString> {
-        self.metas.get(&path).map(|meta| meta.title(lang))
-    }
-
-    pub fn description(&self, path: IPath, lang: Language) -> Option<IString> {
-        self.metas.get(&path).map(|meta| meta.description(lang))
-    }
-}
-
-impl ImplicitClone for MetaStore {}
-
-impl Index<IPath> for MetaStore {
-    type Output = Meta;
-
-    fn index(&self, index: IPath) -> &Self::Output {
-        self.metas.get_ref(&index).unwrap()
-    }
-}
-
-pub fn process_metas(
-    source_dir_path: IPath,
-    languages: LanguageStore,
-    tags: TagStore,
-) -> MetaStore {
-    MetaStore {
-        metas: all_path_ids(source_dir_path.clone())
-            .map(|path_id| {
-                let indices =
-                    all_possible_indices(source_dir_path.clone(), path_id.clone(), "meta".into())
-                        .collect_vec();
-                assert!(
-                    !indices.is_empty(),
-                    "missing meta file for the page path `{}`",
-                    path_id.display()
-                );
-                assert!(
-                    indices.len() == 1,
-                    "there should be exactly 1 page meta file for the page path `{}`, found: {}",
-                    path_id.display(),
-                    indices
-                        .iter()
-                        .map(|index| format!("`{}`", index.display()))
-                        .join(", ")
-                );
-                (path_id, indices.into_iter().next().unwrap())
-            })
-            .map(|(path_id, path)| {
-                let mut table =
-                    toml::from_str::<toml::Table>(&fs::read_to_string(&path).unwrap()).unwrap();
-                (
-                    path_id.clone(),
-                    Meta {
-                        path: path_id.clone(),
-                        tags: match table.remove("tags") {
-                            Some(tags @ toml::Value::Array(..)) => tags.try_into().unwrap(),
-                            Some(tags) => [tags.try_into().unwrap()].into(),
-                            None => IArray::<IString>::EMPTY,
-                        }
-                        .iter()
-                        .map(|tag_id| {
-                            tags.get(tag_id.clone()).unwrap_or_else(|| {
-                                panic!(
-                                    "tag `{tag_id}` used by page meta `{}` {}",
-                                    path.display(),
-                                    "is not defined in the tags file"
-                                )
-                            })
-                        })
-                        .collect(),
-                        default_info: Info {
-                            title: table
-                                .remove("title")
-                                .unwrap_or_else(|| {
-                                    panic!(
-                                        "missing default `title` in page meta {}",
-                                        path.display()
-                                    )
-                                })
-                                .try_into()
-                                .unwrap(),
-                            description: table
-                                .remove("description")
-                                .unwrap_or_else(|| {
-                                    panic!(
-                                        "missing default `description` in page meta {}",
-                                        path.display()
-                                    )
-                                })
-                                .try_into()
-                                .unwrap(),
-                        },
-                        infos: table
-                            .into_iter()
-                            .map(|(lang_id, info)| {
-                                let lang_id: IString = lang_id.into();
-                                let lang = languages.get(lang_id.clone());
-                                (
-                                    lang.ok().unwrap_or_else(|| {
-                                        panic!(
-                                            "language `{lang_id}` used by tag `{}` {}",
-                                            path.display(), // lol fmt breaks if line too long
-                                            "is not defined in the languages file"
-                                        )
-                                    }),
-                                    info.try_into().unwrap(),
-                                )
-                            })
-                            .collect(),
-                    },
-                )
-            })
-            .collect(),
-    }
-}
+use std::collections::BTreeMap;
+use std::ops::Index;
+use std::ops::Range;
+
+use implicit_clone::sync::IArray;
+use implicit_clone::sync::IMap;
+use implicit_clone::sync::IString;
+use implicit_clone::ImplicitClone;
+use itertools::Itertools;
+use serde::de::DeserializeOwned;
+use toml::Spanned;
+
+use crate::date::Date;
+use crate::error::Diagnostics;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::front_matter::read_front_matter;
+use crate::language::Language;
+use crate::language::LanguageStore;
+use crate::sync::path::IPath;
+use crate::tag::Tag;
+use crate::tag::TagStore;
+use crate::utils::all_path_ids;
+use crate::utils::all_possible_indices;
+use crate::utils::check_dir;
+use crate::utils::GetRef;
+use crate::utils::Info;
+use crate::utils::SpannedTable;
+use crate::utils::TomlSource;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Meta {
+    pub path: IPath,
+    /// Files the meta was loaded from: the meta file and page files with front matter.
+    pub filepaths: IArray<IPath>,
+    pub tags: IArray<Tag>,
+    pub default_info: Info<IString>,
+    pub infos: IMap<Language, Info<Option<IString>>>,
+    /// Date the page was created at, if set explicitly.
+    pub created: Option<Date>,
+    /// Date the page was last updated at, if set explicitly.
+    pub updated: Option<Date>,
+    /// Number of heading levels listed in the table of contents, `None` if there is none.
+    pub toc: Option<u8>,
+    /// Whether the page is a draft, unless overridden for a language in `drafts`.
+    pub draft: bool,
+    pub drafts: IMap<Language, bool>,
+}
+
+impl Meta {
+    /// Whether the page is a draft in `lang`. Drafts are only built with `--drafts`.
+    pub fn is_draft(&self, lang: Language) -> bool {
+        self.drafts.get(&lang).unwrap_or(self.draft)
+    }
+
+    /// Whether the page is tagged with `tag` or with any tag nested in it.
+    pub fn has_tag(&self, tag: Tag, tags: TagStore) -> bool {
+        self.tags
+            .iter()
+            .any(|own| tags.is_within(own.clone(), tag.clone()))
+    }
+
+    /// Title and description in `lang`, or in the first of its fallbacks they are translated to.
+    pub fn info(&self, lang: Language) -> Info<IString> {
+        Info {
+            title: self.title(lang.clone()),
+            description: self.description(lang),
+        }
+    }
+
+    pub fn title(&self, lang: Language) -> IString {
+        lang.chain()
+            .find_map(|lang| self.infos.get(&lang).and_then(|info| info.title))
+            .unwrap_or_else(|| self.default_info.title.clone())
+    }
+
+    pub fn description(&self, lang: Language) -> IString {
+        lang.chain()
+            .find_map(|lang| self.infos.get(&lang).and_then(|info| info.description))
+            .unwrap_or_else(|| self.default_info.description.clone())
+    }
+}
+
+impl ImplicitClone for Meta {}
+
+#[derive(Debug, Clone)]
+pub struct MetaStore {
+    pub metas: IMap<IPath, Meta>,
+}
+
+impl MetaStore {
+    pub fn get(&self, path: IPath) -> Option<Meta> {
+        self.metas.get(&path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Meta> + '_ {
+        self.metas.values()
+    }
+
+    pub fn iter_ids(&self) -> impl Iterator<Item = &IPath> + '_ {
+        self.metas.keys()
+    }
+
+    /// Metas tagged with `tag` or with any tag nested in it.
+    pub fn iter_by_tag(&self, tag: Tag, tags: TagStore) -> impl Iterator<Item = &Meta> + '_ {
+        self.iter()
+            .filter(move |meta| meta.has_tag(tag.clone(), tags.clone()))
+    }
+
+    pub fn title(&self, path: IPath, lang: Language) -> Option<IString> {
+        self.metas.get(&path).map(|meta| meta.title(lang))
+    }
+
+    pub fn description(&self, path: IPath, lang: Language) -> Option<IString> {
+        self.metas.get(&path).map(|meta| meta.description(lang))
+    }
+}
+
+impl ImplicitClone for MetaStore {}
+
+impl Index<IPath> for MetaStore {
+    type Output = Meta;
+
+    fn index(&self, index: IPath) -> &Self::Output {
+        self.metas.get_ref(&index).unwrap()
+    }
+}
+
+/// Keys of a meta that can be set for a language.
+const TRANSLATED_KEYS: [&str; 3] = ["title", "description", "draft"];
+
+/// Value of a meta key, along with the file it is defined in.
+type MetaValue = (TomlSource, Spanned<toml::Value>);
+
+/// Keys of a meta merged from all of its files.
+#[derive(Default)]
+struct MetaTable {
+    keys: BTreeMap<String, MetaValue>,
+    /// Tables of languages by their ids.
+    langs: BTreeMap<String, LangTable>,
+}
+
+/// Table of a language, merged key by key from all the files defining it. Values of the keys are
+/// located at the table they are in.
+struct LangTable {
+    /// File and span of the first definition of the table.
+    file: TomlSource,
+    span: Range<usize>,
+    keys: BTreeMap<String, MetaValue>,
+}
+
+pub fn process_metas(
+    source_dir_path: IPath,
+    languages: LanguageStore,
+    tags: TagStore,
+    diagnostics: &mut Diagnostics,
+) -> MetaStore {
+    if let Err(err) = check_dir(source_dir_path.clone()) {
+        diagnostics.push(err);
+        return MetaStore {
+            metas: IMap::default(),
+        };
+    }
+    MetaStore {
+        metas: all_path_ids(source_dir_path.clone())
+            .filter_map(|path_id| {
+                let indices =
+                    all_possible_indices(source_dir_path.clone(), path_id.clone(), "meta".into())
+                        .collect_vec();
+                if indices.len() > 1 {
+                    diagnostics.push(
+                        Error::new(
+                            ErrorKind::DuplicateMeta,
+                            format!(
+                                "there should be at most 1 page meta file for the page path `{}`, \
+                                 found: {}",
+                                path_id.display(),
+                                indices
+                                    .iter()
+                                    .map(|index| format!("`{}`", index.display()))
+                                    .join(", ")
+                            ),
+                        )
+                        .with_path(source_dir_path.join(&path_id)),
+                    );
+                    return None;
+                }
+
+                // page files of any language can have front matter instead of (or along with)
+                // the meta file
+                let page_files = languages
+                    .iter()
+                    .flat_map(|lang| {
+                        all_possible_indices(
+                            source_dir_path.clone(),
+                            path_id.clone(),
+                            lang.id.clone(),
+                        )
+                        .map(move |path| (path, lang.clone()))
+                    })
+                    .sorted_by(|(a, _), (b, _)| a.cmp(b))
+                    .collect_vec();
+                let mut failed = false;
+                let sources = indices
+                    .into_iter()
+                    .map(|path| {
+                        TomlSource::read::<SpannedTable>(path)
+                            .map(|(file, table)| Some((file, table, None)))
+                    })
+                    .chain(page_files.into_iter().map(|(path, lang)| {
+                        read_front_matter(path).map(|front_matter| {
+                            front_matter.map(|(file, table)| (file, table, Some(lang)))
+                        })
+                    }))
+                    .filter_map(|source| {
+                        source
+                            .map_err(|err| {
+                                diagnostics.push(err);
+                                failed = true;
+                            })
+                            .ok()?
+                    })
+                    .collect_vec();
+                if failed {
+                    return None;
+                }
+                if sources.is_empty() {
+                    diagnostics.push(
+                        Error::new(
+                            ErrorKind::MissingMeta,
+                            format!(
+                                "missing meta file or front matter for the page path `{}`",
+                                path_id.display()
+                            ),
+                        )
+                        .with_path(source_dir_path.join(&path_id)),
+                    );
+                    return None;
+                }
+                Some((path_id, sources))
+            })
+            .collect_vec()
+            .into_iter()
+            .filter_map(|(path_id, sources)| {
+                let filepaths = sources
+                    .iter()
+                    .map(|(file, _, _)| file.path.clone())
+                    .collect();
+                // required keys missing from all the sources are reported in the first one
+                let main_file = sources[0].0.clone();
+                let MetaTable {
+                    keys: mut table,
+                    langs,
+                } = merge_meta_sources(sources, languages.default.clone(), diagnostics);
+
+                let page_tags = match table.remove("tags") {
+                    None => Ok(IArray::EMPTY),
+                    Some((file, value)) => {
+                        let span = value.span();
+                        file.convert_list("tags", value).map(|tag_ids| {
+                            tag_ids
+                                .iter()
+                                .filter_map(|tag_id| {
+                                    tags.get(tag_id.clone()).or_else(|| {
+                                        diagnostics.push(file.error(
+                                            ErrorKind::UnknownTag,
+                                            Some(span.clone()),
+                                            format!(
+                                                "tag `{tag_id}` is not defined in the tags \
+                                                 directory"
+                                            ),
+                                        ));
+                                        None
+                                    })
+                                })
+                                .collect()
+                        })
+                    }
+                };
+                let mut take_date = |key| {
+                    let Some((file, value)) = table.remove(key) else {
+                        return Ok(None);
+                    };
+                    match value.get_ref() {
+                        toml::Value::Datetime(datetime) if datetime.date.is_some() => {
+                            Ok(Date::from_toml(datetime))
+                        }
+                        _ => Err(file.error(
+                            ErrorKind::InvalidValue,
+                            Some(value.span()),
+                            format!("invalid `{key}`: expected a date like `2025-01-31`"),
+                        )),
+                    }
+                };
+                let created = take_date("created");
+                let updated = take_date("updated");
+                let toc = match table.remove("toc") {
+                    None => Ok(Some(6)),
+                    Some((file, value)) => match *value.get_ref() {
+                        toml::Value::Boolean(enabled) => Ok(enabled.then_some(6)),
+                        toml::Value::Integer(depth @ 1..=6) => Ok(Some(depth as u8)),
+                        _ => Err(file.error(
+                            ErrorKind::InvalidValue,
+                            Some(value.span()),
+                            "invalid `toc`: expected a boolean or a depth from 1 to 6",
+                        )),
+                    },
+                };
+                let draft = match table.remove("draft") {
+                    None => Ok(false),
+                    Some((file, value)) => file.convert("draft", value),
+                };
+                let title = take_required(&mut table, "title", &main_file);
+                let description = take_required(&mut table, "description", &main_file);
+                // keys left are not known, unless they are languages set to something else than
+                // a table
+                for (key, (file, value)) in table {
+                    if file
+                        .language(&languages, &key, value.span(), diagnostics)
+                        .is_some()
+                    {
+                        diagnostics.push(file.error(
+                            ErrorKind::InvalidValue,
+                            Some(value.span()),
+                            format!("invalid `{key}`: expected a table of translations"),
+                        ));
+                    }
+                }
+                let mut drafts = Vec::new();
+                let infos = langs
+                    .into_iter()
+                    .filter_map(|(lang_id, mut lang_table)| {
+                        let lang = lang_table.file.language(
+                            &languages,
+                            &lang_id,
+                            lang_table.span.clone(),
+                            diagnostics,
+                        )?;
+                        let keys = &mut lang_table.keys;
+                        let mut take = |result: Result<_, Error>| {
+                            result.map_err(|err| diagnostics.push(err)).ok().flatten()
+                        };
+                        let title = take(take_translated(keys, &lang_id, "title"));
+                        let description = take(take_translated(keys, &lang_id, "description"));
+                        // drafts can be overridden for a language
+                        let draft = take_translated(keys, &lang_id, "draft");
+                        if let Some(draft) =
+                            draft.map_err(|err| diagnostics.push(err)).ok().flatten()
+                        {
+                            drafts.push((lang.clone(), draft));
+                        }
+                        Some((lang, Info { title, description }))
+                    })
+                    .collect();
+                match (page_tags, title, description, created, updated, toc, draft) {
+                    (
+                        Ok(page_tags),
+                        Ok(title),
+                        Ok(description),
+                        Ok(created),
+                        Ok(updated),
+                        Ok(toc),
+                        Ok(draft),
+                    ) => Some((
+                        path_id.clone(),
+                        Meta {
+                            path: path_id,
+                            filepaths,
+                            tags: page_tags,
+                            default_info: Info { title, description },
+                            infos,
+                            created,
+                            updated,
+                            toc,
+                            draft,
+                            drafts: drafts.into_iter().collect(),
+                        },
+                    )),
+                    (page_tags, title, description, created, updated, toc, draft) => {
+                        diagnostics.extend(
+                            [
+                                page_tags.err(),
+                                title.err(),
+                                description.err(),
+                                created.err(),
+                                updated.err(),
+                                toc.err(),
+                                draft.err(),
+                            ]
+                            .into_iter()
+                            .flatten(),
+                        );
+                        None
+                    }
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Merge the tables of the meta file and of front matters. Every key can be defined only once,
+/// except for tables of languages, which are merged key by key. Front matter of a page file in a
+/// language other than the `default` one is of that language only: its `title`, `description` and
+/// `draft` are the ones of the language, and it can not set anything else.
+fn merge_meta_sources(
+    sources: Vec<(TomlSource, SpannedTable, Option<Language>)>,
+    default: Language,
+    diagnostics: &mut Diagnostics,
+) -> MetaTable {
+    let mut merged = MetaTable::default();
+    for (file, table, lang) in sources {
+        let scope = lang.filter(|lang| *lang != default);
+        for (key, value) in table {
+            let span = value.span();
+            let is_table = matches!(value.get_ref(), toml::Value::Table(_));
+            match &scope {
+                Some(lang) if TRANSLATED_KEYS.contains(&key.as_str()) => {
+                    merged.insert_translated(&lang.id, &file, span, key, value, diagnostics);
+                }
+                Some(lang) if !is_table || key != *lang.id => diagnostics.push(file.error(
+                    ErrorKind::InvalidValue,
+                    Some(span),
+                    format!(
+                        "`{key}` can not be set in front matter of a page file in `{}`, only \
+                         {} and the `{}` table can",
+                        lang.id,
+                        TRANSLATED_KEYS.map(|key| format!("`{key}`")).join(", "),
+                        lang.id,
+                    ),
+                )),
+                _ if is_table => {
+                    let toml::Value::Table(inner) = value.into_inner() else {
+                        unreachable!("value should be a table");
+                    };
+                    for (inner_key, inner_value) in inner {
+                        let inner_value = Spanned::new(span.clone(), inner_value);
+                        merged.insert_translated(
+                            &key,
+                            &file,
+                            span.clone(),
+                            inner_key,
+                            inner_value,
+                            diagnostics,
+                        );
+                    }
+                }
+                _ => match merged.keys.get(&key) {
+                    Some((first_file, _)) => {
+                        diagnostics.push(conflict(&file, span, &key, first_file));
+                    }
+                    None => {
+                        merged.keys.insert(key, (file.clone(), value));
+                    }
+                },
+            }
+        }
+    }
+    merged
+}
+
+impl MetaTable {
+    /// Set `key` of the table of the language `lang_id`, whose table is at `span` of the `file`.
+    fn insert_translated(
+        &mut self,
+        lang_id: &str,
+        file: &TomlSource,
+        span: Range<usize>,
+        key: String,
+        value: Spanned<toml::Value>,
+        diagnostics: &mut Diagnostics,
+    ) {
+        let table = self
+            .langs
+            .entry(lang_id.to_owned())
+            .or_insert_with(|| LangTable {
+                file: file.clone(),
+                span: span.clone(),
+                keys: BTreeMap::new(),
+            });
+        match table.keys.get(&key) {
+            Some((first_file, _)) => {
+                let name = format!("{lang_id}.{key}");
+                diagnostics.push(conflict(file, span, &name, first_file));
+            }
+            None => {
+                table.keys.insert(key, (file.clone(), value));
+            }
+        }
+    }
+}
+
+/// Error of the key `name` at `span` of the `file` that is already defined in the `first_file`.
+fn conflict(file: &TomlSource, span: Range<usize>, name: &str, first_file: &TomlSource) -> Error {
+    file.error(
+        ErrorKind::ConflictingMeta,
+        Some(span),
+        format!(
+            "`{name}` is already defined in `{}`",
+            first_file.path.display()
+        ),
+    )
+}
+
+/// Remove an optional `key` from the `table` of the language `lang_id` and convert it.
+fn take_translated<T: DeserializeOwned>(
+    table: &mut BTreeMap<String, MetaValue>,
+    lang_id: &str,
+    key: &str,
+) -> Result<Option<T>, Error> {
+    table
+        .remove(key)
+        .map(|(file, value)| file.convert(&format!("{lang_id}.{key}"), value))
+        .transpose()
+}
+
+/// Remove a required `key` from the `table` and convert it. If it is missing, the error is
+/// located in the `main_file`.
+fn take_required<T: DeserializeOwned>(
+    table: &mut BTreeMap<String, MetaValue>,
+    key: &str,
+    main_file: &TomlSource,
+) -> Result<T, Error> {
+    match table.remove(key) {
+        Some((file, value)) => file.convert(key, value),
+        None => Err(main_file.error(
+            ErrorKind::MissingKey,
+            None,
+            format!("missing required `{key}`"),
+        )),
+    }
+}
@@ -3,12 +3,17 @@ use implicit_clone::sync::IMap;
 use implicit_clone::ImplicitClone;
 use itertools::Itertools;
 
+use crate::error::Diagnostics;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::git::GitDates;
 use crate::language::Language;
 use crate::language::LanguageStore;
 use crate::meta::MetaStore;
 use crate::sync::path::IPath;
 use crate::utils::all_path_ids;
 use crate::utils::all_possible_indices;
+use crate::utils::check_dir;
 
 #[derive(Debug, Clone)]
 pub struct PageStore {
@@ -23,6 +28,46 @@ impl PageStore {
     pub fn get(&self, path: IPath) -> Option<IMap<Language, IPath>> {
         self.pages.get(&path)
     }
+
+    /// Leave out page files that are drafts in their language, along with pages left without any.
+    pub fn without_drafts(&self, metas: MetaStore) -> PageStore {
+        PageStore {
+            pages: self
+                .iter()
+                .filter_map(|(path_id, page)| {
+                    let meta = metas.get(path_id.clone())?;
+                    let page = page
+                        .iter()
+                        .filter(|(lang, _)| !meta.is_draft((*lang).clone()))
+                        .map(|(lang, path)| (lang.clone(), path.clone()))
+                        .collect::<IMap<_, _>>();
+                    (!page.is_empty()).then(|| (path_id.clone(), page))
+                })
+                .collect(),
+        }
+    }
+
+    /// Page files last committed before the page file of the same page in the `default` language,
+    /// mapped to the latter. Page files that are not committed are never outdated.
+    pub fn outdated_translations(&self, default: Language, git: &GitDates) -> IMap<IPath, IPath> {
+        self.iter()
+            .filter_map(|(_, page)| {
+                let source = page.get(&default)?;
+                let source_date = git.last_commit_date(source.clone())?;
+                Some(
+                    page.iter()
+                        .filter(|(lang, _)| **lang != default)
+                        .filter(|(_, path)| {
+                            git.last_commit_date((*path).clone())
+                                .is_some_and(|date| date < source_date)
+                        })
+                        .map(move |(_, path)| (path.clone(), source.clone()))
+                        .collect_vec(),
+                )
+            })
+            .flatten()
+            .collect()
+    }
 }
 
 impl ImplicitClone for PageStore {}
@@ -30,36 +75,53 @@ impl ImplicitClone for PageStore {}
 pub fn process_pages(
     src_dir_path: IPath,
     languages: LanguageStore,
-    metas: MetaStore, // FIXME check if page is valid
+    metas: MetaStore,
+    diagnostics: &mut Diagnostics,
 ) -> PageStore {
+    if let Err(err) = check_dir(src_dir_path.clone()) {
+        diagnostics.push(err);
+        return PageStore {
+            pages: IMap::default(),
+        };
+    }
     PageStore {
         pages: languages
             .into_iter()
             .flat_map(|lang| {
                 all_path_ids(src_dir_path.clone()).map(move |path| (lang.clone(), path))
             })
-            .flat_map(|(lang, path_id)| {
-                assert!(
-                    metas.get(path_id.clone()).is_some(),
-                    "missing meta for page `{}`",
-                    path_id.display()
-                );
-
+            // pages without a valid meta are already reported by `process_metas`
+            .filter(|(_, path_id)| metas.get(path_id.clone()).is_some())
+            .filter_map(|(lang, path_id)| {
                 let indices =
                     all_possible_indices(src_dir_path.clone(), path_id.clone(), lang.id.clone())
                         .collect::<IArray<_>>();
-                assert!(
-                    indices.len() <= 1,
-                    "there should not be multiple `{}` page files for the path `{}`",
-                    lang.id,
-                    path_id.display()
-                );
-                indices
-                    .into_iter()
-                    .map(move |index| (lang.clone(), path_id.clone(), index))
+                if indices.len() > 1 {
+                    diagnostics.push(
+                        Error::new(
+                            ErrorKind::DuplicatePage,
+                            format!(
+                                "there should not be multiple `{}` page files for the path `{}`, \
+                                 found: {}",
+                                lang.id,
+                                path_id.display(),
+                                indices
+                                    .iter()
+                                    .map(|index| format!("`{}`", index.display()))
+                                    .join(", ")
+                            ),
+                        )
+                        .with_path(src_dir_path.join(&path_id)),
+                    );
+                    return None;
+                }
+                let index = indices.iter().next()?.clone();
+                Some((lang, path_id, index))
             })
             .into_group_map_by(|(_, path_id, _)| path_id.clone())
             .into_iter()
+            // the group map is unordered, keep pages sorted for deterministic output
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
             .map(|(path_id, indices)| {
                 (
                     path_id,
@@ -1,182 +1,561 @@
-use std::cell::RefCell;
-use std::fs::File;
-use std::io;
-use std::io::Write;
-
-use comrak::html::format_document_with_formatter;
-use comrak::html::format_node_default;
-use comrak::html::ChildRendering;
-use comrak::html::Context;
-use comrak::nodes::AstNode;
-use comrak::nodes::NodeCode;
-use comrak::nodes::NodeCodeBlock;
-use comrak::nodes::NodeHeading;
-use comrak::nodes::NodeLink;
-use comrak::nodes::NodeValue;
-use comrak::parse_document;
-use comrak::Arena;
-use comrak::Options;
-use comrak::Plugins;
-use implicit_clone::sync::IArray;
-use implicit_clone::sync::IString;
-use implicit_clone::ImplicitClone;
-use itertools::Itertools;
-
-use crate::i18n::I18nStore;
-use crate::language::Language;
-use crate::meta::MetaStore;
-use crate::page::PageStore;
-use crate::sync::path::IPath;
-use crate::sync::path::ToIPath;
-
-#[derive(Debug, Clone)]
-pub struct RenderCtx {
-    pub lang: Language,
-    pub i18ns: I18nStore,
-    pub metas: MetaStore,
-    pub pages: PageStore,
-}
-
-impl ImplicitClone for RenderCtx {}
-
-thread_local! {
-    static RENDER_CONTEXT: RefCell<Option<RenderCtx>> = None.into();
-}
-
-pub fn my_render(
-    path: IPath,
-    content: IString,
-    ctx: RenderCtx,
-    options: &Options,
-    plugins: &Plugins,
-) {
-    let arena = Arena::new();
-
-    let root = parse_document(&arena, &content, options);
-
-    let mut file = File::create(path.clone()).unwrap();
-
-    RENDER_CONTEXT.with(|r_ctx| *r_ctx.borrow_mut() = Some(ctx));
-    format_document_with_formatter(root, options, &mut file, plugins, my_formatter).unwrap();
-}
-
-fn my_formatter<'a>(
-    context: &mut Context,
-    node: &'a AstNode<'a>,
-    entering: bool,
-) -> io::Result<ChildRendering> {
-    let ctx = RENDER_CONTEXT.with(|my_data| my_data.borrow().as_ref().unwrap().clone());
-
-    let borrow = node.data.borrow_mut();
-    match borrow.value {
-        NodeValue::CodeBlock(NodeCodeBlock { ref info, .. }) if entering => {
-            context.write_all(br#"<div class="code">"#)?;
-
-            // header
-            context.write_all(b"<div class=\"header\">")?;
-            // code
-            context.write_all(b"<span>")?;
-            context.write_all(info.as_bytes())?;
-            context.write_all(b"</span>")?;
-            // copy
-            context.write_all(b"<span copy>")?;
-            context.write_all(
-                ctx.i18ns
-                    .display("code_copy".into(), ctx.lang.clone())
-                    .unwrap()
-                    .as_bytes(),
-            )?;
-            context.write_all(b"</span>")?;
-            context.write_all(b"</div>\n")?;
-
-            // content
-            drop(borrow);
-            format_node_default(context, node, entering)?;
-
-            context.write_all(b"</div>\n")?;
-            Ok(ChildRendering::HTML)
-        }
-        NodeValue::Link(NodeLink { ref url, .. }) if entering => {
-            let path_id = url.split('#').next().unwrap().to_ipath();
-
-            let page = ctx.pages.get(path_id.clone());
-            let title = node
-                .children()
-                .next()
-                .is_none()
-                .then_some(())
-                .filter(|()| page.is_some())
-                .map(|()| ctx.metas.title(path_id, ctx.lang.clone()).unwrap())
-                .unwrap_or_default();
-
-            match page.map(|page| page.keys().cloned().collect::<IArray<_>>()) {
-                Some(available_languages) if available_languages.contains(&ctx.lang) => {
-                    context.write_all(br#"<a href=""#)?;
-                    context.escape_href(format!("/secdb/{}/{}", ctx.lang.id, url).as_bytes())?;
-                    context.write_all(br#"">"#)?;
-                    context.write_all(title.as_bytes())?;
-                    Ok(ChildRendering::HTML)
-                }
-                Some(_) => {
-                    context.write_all(title.as_bytes())?;
-                    Ok(ChildRendering::HTML)
-                }
-                None => {
-                    drop(borrow);
-                    format_node_default(context, node, entering)
-                }
-            }
-        }
-        NodeValue::Link(NodeLink { ref url, .. }) if !entering => {
-            match ctx
-                .pages
-                .get(url.to_ipath())
-                .map(|page| page.keys().cloned().collect::<IArray<_>>())
-            {
-                Some(available_languages) if !available_languages.contains(&ctx.lang) => {
-                    for lang in available_languages {
-                        context.write_all(br#"<a href=""#)?;
-                        context.escape_href(format!("/secdb/{}/{}", lang.id, url).as_bytes())?;
-                        context.write_all(br#""><sup>("#)?;
-                        context.write_all(lang.id.as_bytes())?;
-                        context.write_all(br#")</sup></a>"#)?;
-                    }
-
-                    Ok(ChildRendering::HTML)
-                }
-                _ => {
-                    if url.starts_with("https://") || url.starts_with("http://") {
-                        writeln!(context, "<sup>(⮥)</sup>")?;
-                    }
-                    drop(borrow);
-                    format_node_default(context, node, entering)
-                }
-            }
-        }
-        NodeValue::Heading(NodeHeading { level, .. }) if entering => {
-            let fragment = node
-                .descendants()
-                .filter(|n| !n.same_node(node))
-                .flat_map(|node| match node.data.borrow().value {
-                    NodeValue::Text(ref text)
-                    | NodeValue::Code(NodeCode {
-                        literal: ref text, ..
-                    }) => text.split(' ').map(|s| s.to_lowercase()).collect(),
-                    _ => vec![],
-                })
-                .filter(|s| !s.is_empty())
-                .join("-");
-
-            writeln!(context, r#"<h{level} id="{fragment}">"#)?;
-            Ok(ChildRendering::HTML)
-        }
-        NodeValue::Heading(NodeHeading { level, .. }) if !entering => {
-            writeln!(context, r#"<span class="link">🔗</span></h{level}>"#)?;
-            Ok(ChildRendering::HTML)
-        }
-        _ => {
-            drop(borrow);
-            format_node_default(context, node, entering)
-        }
-    }
-}
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use comrak::html::format_document_with_formatter;
+use comrak::html::format_node_default;
+use comrak::html::ChildRendering;
+use comrak::html::Context;
+use comrak::nodes::AlertType;
+use comrak::nodes::AstNode;
+use comrak::nodes::NodeAlert;
+use comrak::nodes::NodeCode;
+use comrak::nodes::NodeCodeBlock;
+use comrak::nodes::NodeHeading;
+use comrak::nodes::NodeLink;
+use comrak::nodes::NodeMath;
+use comrak::nodes::NodeValue;
+use comrak::parse_document;
+use comrak::Arena;
+use comrak::Options;
+use comrak::Plugins;
+use implicit_clone::sync::IArray;
+use implicit_clone::sync::IString;
+use implicit_clone::ImplicitClone;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::code::split_highlighted_lines;
+use crate::code::CodeInfo;
+use crate::code::Line;
+use crate::diagram::dot_to_svg;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::i18n::I18nStore;
+use crate::language::Language;
+use crate::math::latex_to_mathml;
+use crate::meta::MetaStore;
+use crate::page::PageStore;
+use crate::sync::path::IPath;
+use crate::sync::path::ToIPath;
+use crate::utils::escape_xml;
+
+#[derive(Debug, Clone)]
+pub struct RenderCtx {
+    pub lang: Language,
+    pub base_url: IString,
+    pub i18ns: I18nStore,
+    pub metas: MetaStore,
+    pub pages: PageStore,
+    /// Whether pages missing in a language are rendered in it from the default language.
+    pub fallback_pages: bool,
+}
+
+impl ImplicitClone for RenderCtx {}
+
+thread_local! {
+    static RENDER_CONTEXT: RefCell<Option<RenderCtx>> = None.into();
+    static RENDER_ERRORS: RefCell<Vec<Error>> = Vec::new().into();
+}
+
+/// Whether the `url` is a path id of a page (e.g. `file` or `file#usage`), as opposed to absolute
+/// paths, anchors and URLs with a scheme (e.g. `https://...` or `mailto:...`).
+pub fn is_page_link(url: &str) -> bool {
+    let has_scheme = url.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    });
+    !(has_scheme || url.starts_with('/') || url.starts_with('#'))
+}
+
+/// Pages that markdown `content` links to, each listed once.
+pub fn linked_pages(content: &str, options: &Options, pages: PageStore) -> Vec<IPath> {
+    let arena = Arena::new();
+    parse_document(&arena, content, options)
+        .descendants()
+        .filter_map(|node| match node.data.borrow().value {
+            NodeValue::Link(NodeLink { ref url, .. }) if is_page_link(url) => {
+                Some(url.split('#').next().unwrap().to_ipath())
+            }
+            _ => None,
+        })
+        .filter(|path_id| pages.get(path_id.clone()).is_some())
+        .unique()
+        .collect()
+}
+
+/// Id of a heading, which its anchor links to: lowercase words of its text joined by `-`.
+pub fn heading_id<'a>(heading: &'a AstNode<'a>) -> String {
+    heading
+        .descendants()
+        .filter(|n| !n.same_node(heading))
+        .flat_map(|node| match node.data.borrow().value {
+            NodeValue::Text(ref text)
+            | NodeValue::Code(NodeCode {
+                literal: ref text, ..
+            }) => text.split(' ').map(|s| s.to_lowercase()).collect(),
+            _ => vec![],
+        })
+        .filter(|s| !s.is_empty())
+        .join("-")
+}
+
+/// Heading of a page, as listed in its table of contents and in the search index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Heading {
+    pub level: u8,
+    pub id: IString,
+    pub text: IString,
+}
+
+impl ImplicitClone for Heading {}
+
+/// All headings of a parsed markdown document, in order.
+pub fn headings<'a>(root: &'a AstNode<'a>) -> IArray<Heading> {
+    root.descendants()
+        .filter_map(|node| match node.data.borrow().value {
+            NodeValue::Heading(NodeHeading { level, .. }) => Some(Heading {
+                level,
+                id: heading_id(node).into(),
+                text: plain_text(node).into(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Headings of markdown `content` listed in its table of contents: the ones at most `depth`
+/// levels below the highest level used.
+pub fn table_of_contents(content: &str, options: &Options, depth: u8) -> IArray<Heading> {
+    let arena = Arena::new();
+    let headings = headings(parse_document(&arena, content, options));
+    let top = headings.iter().map(|heading| heading.level).min();
+    headings
+        .iter()
+        .filter(|heading| top.is_some_and(|top| heading.level - top < depth))
+        .cloned()
+        .collect()
+}
+
+/// Text of the `node` and its descendants with whitespace collapsed, leaving out raw HTML.
+pub fn plain_text<'a>(node: &'a AstNode<'a>) -> String {
+    node.descendants()
+        .filter_map(|node| match node.data.borrow().value {
+            NodeValue::Text(ref text)
+            | NodeValue::Code(NodeCode {
+                literal: ref text, ..
+            })
+            | NodeValue::CodeBlock(NodeCodeBlock {
+                literal: ref text, ..
+            })
+            | NodeValue::Math(NodeMath {
+                literal: ref text, ..
+            }) => Some(text.clone()),
+            _ => None,
+        })
+        .join(" ")
+        .split_whitespace()
+        .join(" ")
+}
+
+fn report(error: Error) {
+    RENDER_ERRORS.with(|errors| errors.borrow_mut().push(error));
+}
+
+/// Display of the i18n `key` in the language of the page, or `None` after reporting that it is
+/// missing or invalid.
+fn i18n(ctx: &RenderCtx, key: &str) -> Option<String> {
+    let kind = match ctx.i18ns.contains(key.into()) {
+        true => ErrorKind::InvalidValue,
+        false => ErrorKind::MissingI18n,
+    };
+    ctx.i18ns
+        .format(key.into(), ctx.lang.clone(), &[])
+        .map_err(|message| report(Error::new(kind, message)))
+        .ok()
+}
+
+/// Render markdown `content` into `output`. Returns problems found in the content (e.g. broken
+/// links), which are not related to the file it came from.
+pub fn my_render(
+    output: &mut dyn Write,
+    content: IString,
+    ctx: RenderCtx,
+    options: &Options,
+    plugins: &Plugins,
+) -> io::Result<Vec<Error>> {
+    let arena = Arena::new();
+
+    let root = parse_document(&arena, &content, options);
+
+    RENDER_CONTEXT.with(|r_ctx| *r_ctx.borrow_mut() = Some(ctx));
+    RENDER_ERRORS.with(|errors| errors.borrow_mut().clear());
+    format_document_with_formatter(root, options, output, plugins, my_formatter)?;
+    Ok(RENDER_ERRORS.with(|errors| errors.take()))
+}
+
+fn my_formatter<'a>(
+    context: &mut Context,
+    node: &'a AstNode<'a>,
+    entering: bool,
+) -> io::Result<ChildRendering> {
+    let ctx = RENDER_CONTEXT.with(|my_data| my_data.borrow().as_ref().unwrap().clone());
+
+    let borrow = node.data.borrow_mut();
+    match borrow.value {
+        NodeValue::CodeBlock(NodeCodeBlock {
+            ref info,
+            ref literal,
+            ..
+        }) if entering => {
+            let code_info = CodeInfo::parse(info).unwrap_or_else(|err| {
+                report(Error::new(
+                    ErrorKind::CodeBlock,
+                    format!("invalid info string `{info}`: {err}"),
+                ));
+                CodeInfo {
+                    lang: info
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_owned(),
+                    ..CodeInfo::default()
+                }
+            });
+            let line_count = literal.lines().count();
+            if let Some(range) = code_info
+                .highlighted
+                .iter()
+                .find(|range| *range.end() > line_count)
+            {
+                report(Error::new(
+                    ErrorKind::CodeBlock,
+                    format!(
+                        "highlighted lines `{}-{}` are past the end of the code block ({line_count} \
+                         lines)",
+                        range.start(),
+                        range.end()
+                    ),
+                ));
+            }
+            let diagram = match code_info.lang.as_str() {
+                "dot" | "graphviz" => dot_to_svg(literal)
+                    .map_err(|err| {
+                        report(Error::new(
+                            ErrorKind::Diagram,
+                            format!("invalid diagram: {err}"),
+                        ))
+                    })
+                    .ok(),
+                _ => None,
+            };
+            let lines = (diagram.is_none() && code_info.has_line_features(literal))
+                .then(|| code_info.lines(literal));
+            // the source is copied instead of a diagram, and code is copied without prompts
+            let copy = match (&diagram, &lines) {
+                (Some(_), _) => Some(literal.clone()),
+                (None, Some(_)) => Some(code_info.copy_text(literal)),
+                (None, None) => None,
+            };
+            match copy {
+                Some(copy) => {
+                    context.write_all(br#"<div class="code" data-copy=""#)?;
+                    context.escape(copy.as_bytes())?;
+                    context.write_all(br#"">"#)?;
+                }
+                None => context.write_all(br#"<div class="code">"#)?,
+            }
+
+            // header
+            context.write_all(b"<div class=\"header\">")?;
+            // title or language
+            context.write_all(b"<span>")?;
+            context.escape(
+                code_info
+                    .title
+                    .as_deref()
+                    .unwrap_or(&code_info.lang)
+                    .as_bytes(),
+            )?;
+            context.write_all(b"</span>")?;
+            // copy
+            context.write_all(b"<span copy>")?;
+            if let Some(display) = i18n(&ctx, "code_copy") {
+                context.write_all(display.as_bytes())?;
+            }
+            context.write_all(b"</span>")?;
+            context.write_all(b"</div>\n")?;
+
+            // content
+            if let Some(svg) = diagram {
+                writeln!(context, r#"<div class="diagram">{svg}</div>"#)?;
+            } else if let Some(lines) = lines {
+                write_code_lines(context, &code_info, &lines)?;
+            } else {
+                drop(borrow);
+                format_node_default(context, node, entering)?;
+            }
+
+            context.write_all(b"</div>\n")?;
+            Ok(ChildRendering::HTML)
+        }
+        NodeValue::Link(NodeLink { ref url, .. }) if entering => {
+            let path_id = url.split('#').next().unwrap().to_ipath();
+
+            let page = ctx.pages.get(path_id.clone());
+            let title = node
+                .children()
+                .next()
+                .is_none()
+                .then_some(())
+                .filter(|()| page.is_some())
+                .map(|()| ctx.metas.title(path_id.clone(), ctx.lang.clone()).unwrap())
+                .unwrap_or_default();
+
+            match page.map(|page| page.keys().cloned().collect::<IArray<_>>()) {
+                Some(available_languages)
+                    if ctx.fallback_pages || available_languages.contains(&ctx.lang) =>
+                {
+                    context.write_all(br#"<a href=""#)?;
+                    context.escape_href(
+                        format!("{}/{}/{}", ctx.base_url, ctx.lang.id, url).as_bytes(),
+                    )?;
+                    context.write_all(br#"">"#)?;
+                    context.write_all(title.as_bytes())?;
+                    Ok(ChildRendering::HTML)
+                }
+                Some(_) => {
+                    context.write_all(title.as_bytes())?;
+                    Ok(ChildRendering::HTML)
+                }
+                None => {
+                    if is_page_link(url) {
+                        report(Error::new(
+                            ErrorKind::BrokenLink,
+                            format!("link to unknown page `{}`", path_id.display()),
+                        ));
+                    }
+                    drop(borrow);
+                    format_node_default(context, node, entering)
+                }
+            }
+        }
+        NodeValue::Link(NodeLink { ref url, .. }) if !entering => {
+            match ctx
+                .pages
+                .get(url.to_ipath())
+                .map(|page| page.keys().cloned().collect::<IArray<_>>())
+            {
+                Some(available_languages)
+                    if !ctx.fallback_pages && !available_languages.contains(&ctx.lang) =>
+                {
+                    for lang in available_languages {
+                        context.write_all(br#"<a href=""#)?;
+                        context.escape_href(
+                            format!("{}/{}/{}", ctx.base_url, lang.id, url).as_bytes(),
+                        )?;
+                        context.write_all(br#""><sup>("#)?;
+                        context.write_all(lang.id.as_bytes())?;
+                        context.write_all(br#")</sup></a>"#)?;
+                    }
+
+                    Ok(ChildRendering::HTML)
+                }
+                _ => {
+                    if url.starts_with("https://") || url.starts_with("http://") {
+                        writeln!(context, "<sup>(⮥)</sup>")?;
+                    }
+                    drop(borrow);
+                    format_node_default(context, node, entering)
+                }
+            }
+        }
+        NodeValue::Alert(NodeAlert {
+            alert_type,
+            ref title,
+            ..
+        }) => {
+            if !entering {
+                writeln!(context, "</div>")?;
+                return Ok(ChildRendering::HTML);
+            }
+            let kind = match alert_type {
+                AlertType::Note => "note",
+                AlertType::Tip => "tip",
+                AlertType::Important => "important",
+                AlertType::Warning => "warning",
+                AlertType::Caution => "caution",
+            };
+            writeln!(context, r#"<div class="callout callout-{kind}">"#)?;
+            context.write_all(br#"<p class="callout-title">"#)?;
+            match title {
+                Some(title) => context.escape(title.as_bytes())?,
+                None => match i18n(&ctx, &format!("callout_{kind}")) {
+                    Some(display) => context.write_all(display.as_bytes())?,
+                    None => context.write_all(alert_type.default_title().as_bytes())?,
+                },
+            }
+            writeln!(context, "</p>")?;
+            Ok(ChildRendering::HTML)
+        }
+        NodeValue::Math(NodeMath {
+            display_math,
+            ref literal,
+            ..
+        }) if entering => {
+            match latex_to_mathml(literal, display_math) {
+                Ok(mathml) => context.write_all(mathml.as_bytes())?,
+                Err(err) => {
+                    report(Error::new(
+                        ErrorKind::Math,
+                        format!("invalid math `{literal}`: {err}"),
+                    ));
+                    context.write_all(br#"<code class="math-error">"#)?;
+                    context.escape(literal.as_bytes())?;
+                    context.write_all(b"</code>")?;
+                }
+            }
+            Ok(ChildRendering::HTML)
+        }
+        NodeValue::Heading(NodeHeading { level, .. }) if entering => {
+            let fragment = heading_id(node);
+
+            writeln!(context, r#"<h{level} id="{fragment}">"#)?;
+            Ok(ChildRendering::HTML)
+        }
+        NodeValue::Heading(NodeHeading { level, .. }) if !entering => {
+            writeln!(context, r#"<span class="link">🔗</span></h{level}>"#)?;
+            Ok(ChildRendering::HTML)
+        }
+        _ => {
+            drop(borrow);
+            format_node_default(context, node, entering)
+        }
+    }
+}
+
+/// Render the `lines` of a code block one by one, each with its classes, line number and prefix.
+/// The code of all the lines (except for output) is highlighted at once, so that constructs
+/// spanning several lines are highlighted properly.
+fn write_code_lines(context: &mut Context, info: &CodeInfo, lines: &[Line]) -> io::Result<()> {
+    let code = lines
+        .iter()
+        .filter(|line| !line.output)
+        .map(|line| line.code)
+        .join("\n");
+    let mut highlighted = match context.plugins.render.codefence_syntax_highlighter {
+        Some(highlighter) => {
+            highlighter.write_pre_tag(context, HashMap::new())?;
+            highlighter.write_code_tag(context, HashMap::new())?;
+            let mut html = Vec::new();
+            highlighter.write_highlighted(&mut html, Some(info.highlight_lang()), &code)?;
+            split_highlighted_lines(&String::from_utf8_lossy(&html))
+        }
+        None => {
+            context.write_all(b"<pre><code>")?;
+            code.lines().map(escape_xml).collect()
+        }
+    }
+    .into_iter();
+    for line in lines {
+        let html = match line.output {
+            true => escape_xml(line.code),
+            false => highlighted.next().unwrap_or_default(),
+        };
+        write!(context, r#"<span class="{}">"#, line.classes.join(" "))?;
+        if info.line_numbers {
+            write!(
+                context,
+                r#"<span class="line-number">{}</span>"#,
+                line.number
+            )?;
+        }
+        if let Some((prefix, text)) = line.prefix {
+            write!(context, r#"<span class="{}">"#, prefix.class())?;
+            context.escape(text.as_bytes())?;
+            context.write_all(b"</span>")?;
+        }
+        context.write_all(html.as_bytes())?;
+        context.write_all(b"\n</span>")?;
+    }
+    context.write_all(b"</code></pre>\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use implicit_clone::sync::IMap;
+
+    use super::*;
+    use crate::language::Direction;
+
+    /// HTML of markdown `content`, rendered with math and without highlighting.
+    fn render(content: &str) -> String {
+        let lang = Language {
+            id: "en".into(),
+            display: "English".into(),
+            locale: "en-US".into(),
+            direction: Direction::Ltr,
+            date_format: "%Y-%m-%d".into(),
+            fallbacks: IArray::EMPTY,
+        };
+        let ctx = RenderCtx {
+            lang: lang.clone(),
+            base_url: "".into(),
+            i18ns: I18nStore {
+                i18ns: IMap::default(),
+                default_lang: lang,
+            },
+            metas: MetaStore {
+                metas: IMap::default(),
+            },
+            pages: PageStore {
+                pages: IMap::default(),
+            },
+            fallback_pages: false,
+        };
+        let options = Options {
+            extension: comrak::ExtensionOptions {
+                math_dollars: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        my_render(
+            &mut output,
+            content.into(),
+            ctx,
+            &options,
+            &Plugins::default(),
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn math() {
+        let html = render("Area is $\\pi r^2$.\n\n$$\nx^2\n$$\n");
+        assert!(html.contains("<math><semantics><mrow><mi>π</mi>"));
+        assert!(html.contains(r#"<math display="block">"#));
+    }
+
+    #[test]
+    fn dollars_in_code_spans() {
+        let html = render("Costs `$5`, not `$x$` or `$$y$$`.\n");
+        assert!(html.contains("<code>$5</code>"));
+        assert!(html.contains("<code>$x$</code>"));
+        assert!(html.contains("<code>$$y$$</code>"));
+        assert!(!html.contains("<math"));
+    }
+
+    #[test]
+    fn dollars_in_code_blocks() {
+        let html = render("```\necho $HOME $x$\n$$y$$\n```\n\n```bash linenos\n$ echo $x$\n```\n");
+        assert!(html.contains("echo $HOME $x$\n$$y$$"));
+        assert!(html.contains("echo $x$"));
+        assert!(!html.contains("<math"));
+    }
+}
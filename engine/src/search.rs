@@ -0,0 +1,70 @@
+use comrak::parse_document;
+use comrak::Arena;
+use comrak::Options;
+use implicit_clone::sync::IArray;
+use implicit_clone::sync::IString;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::render::headings;
+use crate::render::plain_text;
+use crate::render::Heading;
+use crate::sync::path::IPath;
+use crate::template::Context;
+use crate::template::TemplateStore;
+
+/// Page to be put into the search index.
+#[derive(Debug, Clone)]
+pub struct SearchPage {
+    pub url: IString,
+    /// Page file, rendered as a template with the `context`.
+    pub filepath: IPath,
+    pub context: Context,
+}
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    url: IString,
+    title: IString,
+    description: IString,
+    tags: Vec<IString>,
+    headings: IArray<Heading>,
+    text: String,
+}
+
+/// Generate the JSON search index of `pages`. Pages that fail to render are left out, as their
+/// problems are reported by their own outputs.
+pub fn search_index(pages: &[SearchPage], templates: &TemplateStore, options: &Options) -> IString {
+    let entries = pages
+        .iter()
+        .filter_map(|page| {
+            let content = templates
+                .compile_and_render(page.filepath.clone(), page.context.clone(), None)
+                .ok()?;
+            let arena = Arena::new();
+            let root = parse_document(&arena, &content, options);
+            let headings = headings(root);
+            let context = &page.context;
+            Some(Entry {
+                url: page.url.clone(),
+                title: context.title.clone().unwrap_or_default(),
+                description: context.description.clone().unwrap_or_default(),
+                tags: context
+                    .page
+                    .tags
+                    .iter()
+                    .filter_map(|tag| {
+                        context
+                            .tags
+                            .title(tag.clone(), context.current_lang.clone())
+                    })
+                    .collect(),
+                headings,
+                text: plain_text(root),
+            })
+        })
+        .collect_vec();
+    serde_json::to_string(&entries)
+        .expect("search index should be serializable")
+        .into()
+}
@@ -0,0 +1,229 @@
+use std::fs;
+use std::iter;
+use std::path::Component;
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use notify::EventKind;
+use notify::RecursiveMode;
+use notify::Watcher;
+use tiny_http::Header;
+use tiny_http::Request;
+use tiny_http::Response;
+use tiny_http::Server;
+
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::sync::path::IPath;
+
+/// Path requested by browsers to wait for the next rebuild.
+const RELOAD_PATH: &str = "/__reload";
+
+/// Script injected into served HTML pages. It waits for the generation to change and reloads.
+const RELOAD_SCRIPT: &str = r#"<script>
+(async generation => {
+    while (true) {
+        try {
+            const response = await fetch(`/__reload?generation=${generation}`);
+            if (Number(await response.text()) !== generation) return location.reload();
+        } catch {
+            await new Promise(resolve => setTimeout(resolve, 1000));
+        }
+    }
+})(GENERATION);
+</script>"#;
+
+/// Count of rebuilds done since the server was started.
+#[derive(Default)]
+struct Generation {
+    value: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl Generation {
+    fn get(&self) -> u64 {
+        *self.value.lock().unwrap()
+    }
+
+    fn increment(&self) {
+        *self.value.lock().unwrap() += 1;
+        self.changed.notify_all();
+    }
+
+    /// Wait until the generation differs from `known`, or the timeout passes.
+    fn wait(&self, known: u64) -> u64 {
+        let value = self.value.lock().unwrap();
+        let (value, _) = self
+            .changed
+            .wait_timeout_while(value, Duration::from_secs(30), |value| *value == known)
+            .unwrap();
+        *value
+    }
+}
+
+/// Serve `out_dir` under the `base_path` at `address`, and call `rebuild` whenever something in
+/// `src_dir` changes. Only returns if it fails to listen or to watch files.
+pub fn serve(
+    address: (&str, u16),
+    out_dir: IPath,
+    base_path: &str,
+    src_dir: IPath,
+    mut rebuild: impl FnMut(),
+) -> Result<(), Error> {
+    let server = Server::http(address).map_err(|err| {
+        Error::new(
+            ErrorKind::Io,
+            format!("failed to listen on {}:{}: {err}", address.0, address.1),
+        )
+    })?;
+    let generation = Arc::new(Generation::default());
+    println!(
+        "serving `{}` on http://{}:{}{base_path}/",
+        out_dir.display(),
+        address.0,
+        address.1
+    );
+
+    thread::spawn({
+        let generation = generation.clone();
+        let base_path = base_path.to_string();
+        move || {
+            for request in server.incoming_requests() {
+                let generation = generation.clone();
+                let out_dir = out_dir.clone();
+                let base_path = base_path.clone();
+                thread::spawn(move || handle(request, &out_dir, &base_path, &generation));
+            }
+        }
+    });
+
+    let (tx, rx) = mpsc::channel();
+    let watch_error = |err| {
+        Error::new(ErrorKind::Io, format!("failed to watch: {err}")).with_path(src_dir.clone())
+    };
+    let mut watcher = notify::recommended_watcher(tx).map_err(watch_error)?;
+    watcher
+        .watch(&src_dir, RecursiveMode::Recursive)
+        .map_err(watch_error)?;
+
+    while let Ok(event) = rx.recv() {
+        // editors usually touch files multiple times per save, wait for them to settle
+        let events = iter::once(event)
+            .chain(iter::from_fn(|| {
+                rx.recv_timeout(Duration::from_millis(100)).ok()
+            }))
+            .filter_map(Result::ok)
+            .filter(|event| !matches!(event.kind, EventKind::Access(_)))
+            .collect::<Vec<_>>();
+        if events.is_empty() {
+            continue;
+        }
+
+        // the build cache takes care of rebuilding only what changed
+        rebuild();
+        generation.increment();
+    }
+    Ok(())
+}
+
+fn handle(request: Request, out_dir: &Path, base_path: &str, generation: &Generation) {
+    let url = request.url().to_string();
+    let (raw_path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let path = percent_decode(raw_path);
+
+    if path == RELOAD_PATH {
+        let known = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("generation="))
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default();
+        let _ = request.respond(Response::from_string(generation.wait(known).to_string()));
+        return;
+    }
+
+    let Some(relative) = path.strip_prefix(base_path) else {
+        let _ = match path.as_str() {
+            "/" => request.respond(redirect(&format!("{base_path}/"))),
+            _ => request.respond(Response::from_string("not found").with_status_code(404)),
+        };
+        return;
+    };
+    let relative = Path::new(relative.trim_start_matches('/'));
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        let _ = request.respond(Response::from_string("forbidden").with_status_code(403));
+        return;
+    }
+
+    let mut file = out_dir.join(relative);
+    if file.is_dir() {
+        if !path.ends_with('/') {
+            let _ = request.respond(redirect(&format!("{raw_path}/")));
+            return;
+        }
+        file = file.join("index.html");
+    }
+    let Ok(mut data) = fs::read(&file) else {
+        let _ = request.respond(Response::from_string("not found").with_status_code(404));
+        return;
+    };
+
+    let extension = file.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    if extension == "html" {
+        let script = RELOAD_SCRIPT.replace("GENERATION", &generation.get().to_string());
+        data.extend_from_slice(script.as_bytes());
+    }
+    let content_type = match extension {
+        "html" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        _ => "application/octet-stream",
+    };
+    let mut response = Response::from_data(data);
+    if let Ok(header) = Header::from_bytes("Content-Type", content_type) {
+        response.add_header(header);
+    }
+    let _ = request.respond(response);
+}
+
+/// Redirect to `location`, or `400` if it is not a valid header value.
+fn redirect(location: &str) -> Response<std::io::Empty> {
+    match Header::from_bytes("Location", location) {
+        Ok(header) => Response::empty(302).with_header(header),
+        Err(()) => Response::empty(400),
+    }
+}
+
+/// Decode `%XX` sequences of a URL path (e.g. non-ASCII page ids).
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) => match u8::from_str_radix(&String::from_utf8_lossy(hex), 16) {
+                Ok(byte) => {
+                    decoded.push(byte);
+                    i += 3;
+                    continue;
+                }
+                Err(_) => decoded.push(b'%'),
+            },
+            (byte, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
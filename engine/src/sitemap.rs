@@ -0,0 +1,60 @@
+use std::fmt::Write;
+
+use implicit_clone::sync::IString;
+
+use crate::date::Date;
+use crate::utils::escape_xml;
+
+/// Page of the sitemap, along with all of its language variants.
+#[derive(Debug, Clone, Hash)]
+pub struct SitemapPage {
+    /// Locales, URLs and last modification dates of the variants.
+    pub variants: Vec<(IString, IString, Option<Date>)>,
+    /// Locale of the variant that `x-default` points to.
+    pub default_lang: IString,
+}
+
+/// Render the sitemap XML of `pages`. Every variant of a page lists all the variants as its
+/// `hreflang` alternates.
+pub fn sitemap(pages: &[SitemapPage]) -> IString {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\" \
+         xmlns:xhtml=\"http://www.w3.org/1999/xhtml\">\n",
+    );
+    for page in pages {
+        for (_, url, lastmod) in &page.variants {
+            let _ = writeln!(xml, "<url>\n<loc>{}</loc>", escape_xml(url));
+            if let Some(lastmod) = lastmod {
+                let _ = writeln!(xml, "<lastmod>{lastmod}</lastmod>");
+            }
+            for (lang, url, _) in &page.variants {
+                let _ = writeln!(
+                    xml,
+                    "<xhtml:link rel=\"alternate\" hreflang=\"{}\" href=\"{}\"/>",
+                    escape_xml(lang),
+                    escape_xml(url)
+                );
+            }
+            if let Some((_, url, _)) = page
+                .variants
+                .iter()
+                .find(|(lang, _, _)| *lang == page.default_lang)
+            {
+                let _ = writeln!(
+                    xml,
+                    "<xhtml:link rel=\"alternate\" hreflang=\"x-default\" href=\"{}\"/>",
+                    escape_xml(url)
+                );
+            }
+            xml.push_str("</url>\n");
+        }
+    }
+    xml.push_str("</urlset>\n");
+    xml.into()
+}
+
+/// Render `robots.txt` allowing everything and pointing to the sitemap.
+pub fn robots_txt(sitemap_url: &str) -> IString {
+    format!("User-agent: *\nAllow: /\n\nSitemap: {sitemap_url}\n").into()
+}
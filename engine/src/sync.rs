@@ -1,4 +1,4 @@
-use std::sync::Arc as Rc;
-
-#[path = "path.rs"]
-pub mod path;
+use std::sync::Arc as Rc;
+
+#[path = "path.rs"]
+pub mod path;
@@ -1,23 +1,35 @@
-use std::fs;
+use std::collections::HashSet;
 use std::iter;
 use std::ops::Index;
+use std::path::Path;
 
 use implicit_clone::sync::IArray;
 use implicit_clone::sync::IMap;
 use implicit_clone::sync::IString;
 use implicit_clone::ImplicitClone;
+use itertools::Itertools;
 
+use crate::error::Diagnostics;
+use crate::error::Error;
+use crate::error::ErrorKind;
 use crate::language::Language;
 use crate::language::LanguageStore;
 use crate::sync::path::IPath;
+use crate::sync::path::ToIPath;
+use crate::utils::check_dir;
 use crate::utils::iter_deep;
-use crate::utils::path_to_parts_and_first;
+use crate::utils::path_id;
 use crate::utils::GetRef;
 use crate::utils::Info;
+use crate::utils::SpannedTable;
+use crate::utils::TomlSource;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Tag {
+    /// Path of the tag file relative to the tags directory (e.g. `networking/routing`).
     pub id: IString,
+    /// Id of the tag this tag is nested in (e.g. `networking` for `networking/routing`).
+    pub parent: Option<IString>,
     pub alt_ids: IArray<IString>,
     pub default_info: Info<IString>,
     pub infos: IMap<Language, Info<Option<IString>>>,
@@ -25,31 +37,23 @@ pub struct Tag {
 
 impl Tag {
     pub fn title(&self, lang: Language) -> IString {
-        self.infos
-            .get(&lang)
-            .and_then(|info| info.title)
+        lang.chain()
+            .find_map(|lang| self.infos.get(&lang).and_then(|info| info.title))
             .unwrap_or_else(|| self.default_info.title.clone())
     }
 
     pub fn description(&self, lang: Language) -> IString {
-        self.infos
-            .get(&lang)
-            .and_then(|info| info.description)
+        lang.chain()
+            .find_map(|lang| self.infos.get(&lang).and_then(|info| info.description))
             .unwrap_or_else(|| self.default_info.description.clone())
     }
 
+    /// Title and description in `lang`, or in the first of its fallbacks they are translated to.
     pub fn info(&self, lang: Language) -> Info<IString> {
-        self.infos
-            .get(&lang)
-            .map(|info| Info {
-                title: info
-                    .title
-                    .unwrap_or_else(|| self.default_info.title.clone()),
-                description: info
-                    .description
-                    .unwrap_or_else(|| self.default_info.description.clone()),
-            })
-            .unwrap_or_else(|| self.default_info.clone())
+        Info {
+            title: self.title(lang.clone()),
+            description: self.description(lang),
+        }
     }
 }
 
@@ -84,6 +88,48 @@ impl TagStore {
     pub fn iter_ids(&self) -> impl Iterator<Item = &IString> + '_ {
         self.tags.keys()
     }
+
+    /// Tags that are not nested in other tags.
+    pub fn roots(&self) -> impl Iterator<Item = Tag> + '_ {
+        self.iter()
+            .filter(|tag| tag.parent.is_none())
+            .unique_by(|tag| tag.id.clone())
+            .cloned()
+    }
+
+    /// Tags directly nested in `tag`.
+    pub fn children(&self, tag: Tag) -> impl Iterator<Item = Tag> + '_ {
+        self.iter()
+            .filter(move |child| child.parent.as_ref() == Some(&tag.id))
+            .unique_by(|child| child.id.clone())
+            .cloned()
+    }
+
+    /// Tags `tag` is nested in, from the closest one.
+    pub fn ancestors(&self, tag: Tag) -> impl Iterator<Item = Tag> + '_ {
+        iter::successors(tag.parent.and_then(|id| self.get(id)), |tag| {
+            tag.parent.clone().and_then(|id| self.get(id))
+        })
+    }
+
+    /// All tags in depth-first order, each followed by the tags nested in it, along with their
+    /// depth (`0` for roots).
+    pub fn tree(&self) -> Vec<(Tag, usize)> {
+        let mut tree = Vec::new();
+        let mut stack = self.roots().map(|tag| (tag, 0)).collect_vec();
+        stack.reverse();
+        while let Some((tag, depth)) = stack.pop() {
+            let children = self.children(tag.clone()).collect_vec();
+            stack.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+            tree.push((tag, depth));
+        }
+        tree
+    }
+
+    /// Whether `tag` is `ancestor` or is nested in it (at any depth).
+    pub fn is_within(&self, tag: Tag, ancestor: Tag) -> bool {
+        tag == ancestor || self.ancestors(tag).any(|tag| tag == ancestor)
+    }
 }
 
 impl ImplicitClone for TagStore {}
@@ -96,45 +142,86 @@ impl Index<IString> for TagStore {
     }
 }
 
-pub fn process_tags(tags_dir_path: IPath, languages: LanguageStore) -> TagStore {
-    TagStore {
-        tags: iter_deep(tags_dir_path)
-            .map(|path| {
-                let mut table =
-                    toml::from_str::<toml::Table>(&fs::read_to_string(&path).unwrap()).unwrap();
-                let (_, id) = path_to_parts_and_first(path);
-                Tag {
-                    id: id.clone(),
-                    alt_ids: match table.remove("alt") {
-                        Some(value @ toml::Value::Array(..)) => value.try_into().unwrap(),
-                        Some(value) => [value.try_into().unwrap()].into(),
-                        None => IArray::EMPTY,
-                    },
-                    default_info: Info {
-                        title: table
-                            .remove("title")
-                            .unwrap_or_else(|| panic!("missing default `title` in tag {id}"))
-                            .try_into()
-                            .unwrap(),
-                        description: table
-                            .remove("description")
-                            .unwrap_or_else(|| panic!("missing default `description` in tag {id}"))
-                            .try_into()
-                            .unwrap(),
+pub fn process_tags(
+    tags_dir_path: IPath,
+    languages: LanguageStore,
+    diagnostics: &mut Diagnostics,
+) -> TagStore {
+    if let Err(err) = check_dir(tags_dir_path.clone()) {
+        diagnostics.push(err);
+        return TagStore {
+            tags: IMap::default(),
+        };
+    }
+    let tags = iter_deep(tags_dir_path.clone())
+        .filter_map(|path| {
+            let (file, mut table) = TomlSource::read::<SpannedTable>(path.clone())
+                .map_err(|err| diagnostics.push(err))
+                .ok()?;
+            // nested tags are in directories named after their parent
+            let path_id = path_id(path.strip_prefix(tags_dir_path.clone()).unwrap());
+            let id: IString = path_id.into_iter_lossy().join("/").into();
+            let parent = path_id
+                .parent()
+                .filter(|parent| *parent != Path::new(""))
+                .map(|parent| parent.to_ipath().into_iter_lossy().join("/").into());
+            let alt_ids = file.take_list(&mut table, "alt");
+            let title = file.take(&mut table, "title", None);
+            let description = file.take(&mut table, "description", None);
+            let infos = table
+                .into_iter()
+                .filter_map(|(lang_id, info)| {
+                    let lang = file.language(&languages, &lang_id, info.span(), diagnostics)?;
+                    let info = file
+                        .convert(&lang_id, info)
+                        .map_err(|err| diagnostics.push(err))
+                        .ok()?;
+                    Some((lang, info))
+                })
+                .collect();
+            match (alt_ids, title, description) {
+                (Ok((alt_ids, _)), Ok(title), Ok(description)) => Some((
+                    path,
+                    Tag {
+                        id,
+                        parent,
+                        alt_ids,
+                        default_info: Info { title, description },
+                        infos,
                     },
-                    infos: table
-                        .into_iter()
-                        .map(|(lang_id, info)| {
-                            let lang_id: IString = lang_id.into();
-                            let lang = languages.get(lang_id.clone());
-                            assert!(
-                                lang.is_ok(),
-                                "{lang_id} used by tag {id} is not defined in the languages file"
-                            );
-                            (lang.unwrap(), info.try_into().unwrap())
-                        })
-                        .collect(),
+                )),
+                (alt_ids, title, description) => {
+                    diagnostics.extend(
+                        [alt_ids.err(), title.err(), description.err()]
+                            .into_iter()
+                            .flatten(),
+                    );
+                    None
+                }
+            }
+        })
+        .collect_vec();
+
+    // parents have to be defined, otherwise the tag is not nested
+    let ids = tags
+        .iter()
+        .map(|(_, tag)| tag.id.clone())
+        .collect::<HashSet<_>>();
+    TagStore {
+        tags: tags
+            .into_iter()
+            .map(|(path, mut tag)| {
+                if let Some(parent) = tag.parent.clone().filter(|parent| !ids.contains(parent)) {
+                    diagnostics.push(
+                        Error::new(
+                            ErrorKind::UnknownTag,
+                            format!("parent tag `{parent}` of tag `{}` is not defined", tag.id),
+                        )
+                        .with_path(path),
+                    );
+                    tag.parent = None;
                 }
+                tag
             })
             .flat_map(|tag| {
                 iter::once(tag.id.clone())
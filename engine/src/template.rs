@@ -2,6 +2,7 @@ use std::fs;
 use std::iter;
 
 use implicit_clone::sync::IArray;
+use implicit_clone::sync::IMap;
 use implicit_clone::sync::IString;
 use implicit_clone::ImplicitClone;
 use itertools::Itertools;
@@ -10,20 +11,34 @@ use upon::Renderer;
 use upon::Template;
 use upon::TemplateRef;
 
+use crate::date::Date;
+use crate::error::Diagnostics;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::front_matter::strip_front_matter;
 use crate::i18n::I18nStore;
 use crate::language::Language;
 use crate::language::LanguageStore;
+use crate::message::Arg;
 use crate::meta::Meta;
 use crate::meta::MetaStore;
+use crate::render::Heading;
 use crate::sync::path::IPath;
 use crate::sync::path::ToIPath;
 use crate::tag::Tag;
 use crate::tag::TagStore;
+use crate::utils::check_dir;
+use crate::utils::escape_xml;
+use crate::utils::is_absolute_url;
 use crate::utils::iter_deep;
 use crate::utils::path_to_parts_and_first;
 
 pub struct TemplateStore {
     engine: upon::Engine<'static>,
+    paths: IMap<IString, IPath>,
+    base_url: IString,
+    /// Whether feeds are generated, which is only done with an absolute base URL.
+    feeds: bool,
 }
 
 trait Render<'render> {
@@ -33,8 +48,12 @@ trait Render<'render> {
         data: impl Serialize,
     ) -> Renderer<'_>;
 
-    fn render(&self, engine: &'render upon::Engine, data: impl Serialize) -> IString {
-        self.renderer(engine, data).to_string().unwrap().into()
+    fn render(
+        &self,
+        engine: &'render upon::Engine,
+        data: impl Serialize,
+    ) -> Result<IString, upon::Error> {
+        self.renderer(engine, data).to_string().map(Into::into)
     }
 }
 
@@ -59,11 +78,33 @@ pub struct PageMeta {
     pub path: IString,
     pub tags: IArray<IString>,
     pub available_in_lang: bool,
+    /// Language of the page file the page is rendered from, which differs from the current one
+    /// for pages rendered from a fallback language.
+    pub content_lang: IString,
     pub languages: IArray<IString>,
 }
 
 impl ImplicitClone for PageMeta {}
 
+/// Page linking to the current page.
+#[derive(Debug, Serialize, Clone, Hash)]
+pub struct Backlink {
+    pub path: IString,
+    pub title: IString,
+}
+
+impl ImplicitClone for Backlink {}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TagNode {
+    pub id: IString,
+    pub parent: Option<IString>,
+    pub depth: usize,
+    pub children: IArray<IString>,
+}
+
+impl ImplicitClone for TagNode {}
+
 #[derive(Debug, Clone)]
 pub struct Context {
     pub current_lang: Language,
@@ -76,6 +117,16 @@ pub struct Context {
     pub page: PageMeta,
     pub title: Option<IString>,
     pub description: Option<IString>,
+    /// Headings listed in the table of contents of the page.
+    pub toc: IArray<Heading>,
+    /// Pages linking to the page in the current language.
+    pub backlinks: IArray<Backlink>,
+    /// Whether the page is a draft, only built with `--drafts`.
+    pub draft: bool,
+    /// Whether the page file was last changed before the one in the default language.
+    pub outdated_translation: bool,
+    /// When the page was last updated.
+    pub updated: Option<Date>,
 }
 
 impl ImplicitClone for Context {}
@@ -86,21 +137,40 @@ impl TemplateStore {
         filepath: IPath,
         context: Context,
         content: Option<IString>,
-    ) -> IString {
-        self.render_with_template(
-            self.engine
-                .compile(fs::read_to_string(filepath).unwrap())
-                .unwrap(),
-            context,
-            content,
-        )
+    ) -> Result<IString, Error> {
+        let source = fs::read_to_string(&filepath).map_err(|err| {
+            Error::new(ErrorKind::Io, err.to_string()).with_path(filepath.clone())
+        })?;
+        // front matter is a part of the meta, not of the content
+        self.engine
+            .compile(strip_front_matter(&source))
+            .and_then(|template| self.render_with_template(template, context, content))
+            .map_err(|err| template_error(err, filepath))
+    }
+
+    /// File the template `id` was loaded from.
+    pub fn path(&self, id: IString) -> IPath {
+        self.paths.get(&id).unwrap_or_default()
+    }
+
+    pub fn iter_paths(&self) -> impl Iterator<Item = &IPath> + '_ {
+        self.paths.values()
     }
 
     // do not forget to add to [engine/README.md] for possible template arguments
-    pub fn render(&self, id: IString, context: Context, content: Option<IString>) -> IString {
+    pub fn render(
+        &self,
+        id: IString,
+        context: Context,
+        content: Option<IString>,
+    ) -> Result<IString, Error> {
         match self.engine.get_template(&id) {
-            None => content.unwrap_or_else(|| panic!("template `{id}` not found")),
-            Some(template) => self.render_with_template(template, context, content),
+            None => content.ok_or_else(|| {
+                Error::new(ErrorKind::Template, format!("template `{id}` not found"))
+            }),
+            Some(template) => self
+                .render_with_template(template, context, content)
+                .map_err(|err| template_error(err, self.path(id))),
         }
     }
 
@@ -118,20 +188,61 @@ impl TemplateStore {
             page,
             title,
             description,
+            toc,
+            backlinks,
+            draft,
+            outdated_translation,
+            updated,
         }: Context,
         content: Option<IString>,
-    ) -> IString {
+    ) -> Result<IString, upon::Error> {
+        // depth is relative to the highest heading level used
+        let toc_top = toc
+            .iter()
+            .map(|heading| heading.level)
+            .min()
+            .unwrap_or_default();
+        let toc = toc
+            .iter()
+            .map(|heading| {
+                upon::value! {
+                    level: heading.level,
+                    depth: heading.level - toc_top,
+                    id: heading.id.clone(),
+                    text: heading.text.clone(),
+                }
+            })
+            .collect_vec();
         template.render(
             &self.engine,
             upon::value! {
                 lang: current_lang.id.clone(),
-                tag: current_tag.map(|tag| tag.id),
+                locale: current_lang.locale.clone(),
+                dir: current_lang.direction.as_str(),
+                tag: current_tag.clone().map(|tag| tag.id),
+                tag_parents: current_tag
+                    .clone()
+                    .map(|tag| {
+                        let mut parents = tags.ancestors(tag).map(|tag| tag.id).collect_vec();
+                        parents.reverse();
+                        parents
+                    })
+                    .unwrap_or_default(),
+                tag_children: current_tag
+                    .map(|tag| tags.children(tag).map(|tag| tag.id).collect_vec())
+                    .unwrap_or_default(),
                 pages: pages
                     .iter()
                     .map(move |(meta, available_languages)| PageMeta {
                         path: meta.path.into_iter_lossy().join("/").into(),
                         tags: meta.tags.iter().cloned().map(|tag| tag.id).collect(),
                         available_in_lang: available_languages.contains(&current_lang),
+                        content_lang: current_lang
+                            .chain()
+                            .find(|lang| available_languages.contains(lang))
+                            .or_else(|| available_languages.first().cloned())
+                            .map(|lang| lang.id)
+                            .unwrap_or_default(),
                         languages: available_languages
                             .iter()
                             .map(|lang| lang.id.clone())
@@ -144,88 +255,160 @@ impl TemplateStore {
                     .cloned()
                     .collect::<IArray<_>>(),
                 tags: tags.iter().map(|tag| tag.id.clone()).unique().collect::<IArray<_>>(),
+                tag_tree: tags
+                    .tree()
+                    .into_iter()
+                    .map(|(tag, depth)| TagNode {
+                        id: tag.id.clone(),
+                        parent: tag.parent.clone(),
+                        depth,
+                        children: tags.children(tag).map(|tag| tag.id).collect(),
+                    })
+                    .collect::<IArray<_>>(),
                 default_lang: languages.default.id,
+                fallback_pages: languages.fallback_pages,
+                base_url: self.base_url.clone(),
+                feeds: self.feeds,
 
                 page: page,
                 title: title,
                 description: description,
+                toc: toc,
+                backlinks: backlinks,
+                draft: draft,
+                outdated_translation: outdated_translation,
+                updated: updated.map(|date| date.0),
                 content: content,
             },
         )
     }
 }
 
+fn template_error(err: upon::Error, path: IPath) -> Error {
+    Error::new(ErrorKind::Template, format!("{err:#}")).with_path(path)
+}
+
+fn get_lang(languages: &LanguageStore, lang_id: String) -> Result<Language, String> {
+    languages
+        .get(lang_id.clone().into())
+        .ok_or_else(|| format!("unknown language `{lang_id}`"))
+}
+
+fn get_tag(tags: &TagStore, tag_id: &str) -> Result<Tag, String> {
+    tags.get(tag_id.into())
+        .ok_or_else(|| format!("unknown tag `{tag_id}`"))
+}
+
+fn get_meta(metas: &MetaStore, path_id: &str) -> Result<Meta, String> {
+    metas
+        .get(path_id.to_ipath())
+        .ok_or_else(|| format!("unknown page `{path_id}`"))
+}
+
 pub fn process_templates(
     template_dir: IPath,
+    base_url: IString,
     languages: LanguageStore,
     i18n: I18nStore,
     tags: TagStore,
     metas: MetaStore,
+    diagnostics: &mut Diagnostics,
 ) -> TemplateStore {
     let mut engine = upon::Engine::new();
 
+    engine.add_filter("escape", |text: String| escape_xml(&text));
     engine.add_filter("eq", |a: String, b: String| a == b);
+    // e.g. `{{ updated | date: lang }}`
+    engine.add_filter("date", {
+        let languages = languages.clone();
+        move |timestamp: i64, lang_id: String| {
+            Ok::<_, String>(Date(timestamp).format(&get_lang(&languages, lang_id)?.date_format))
+        }
+    });
     engine.add_filter("lang_display", {
         let languages = languages.clone();
-        move |lang_id: String| languages.get(lang_id.into()).unwrap().display.to_string()
+        move |lang_id: String| get_lang(&languages, lang_id).map(|lang| lang.display.to_string())
     });
     engine.add_filter("tag_title", {
         let tags = tags.clone();
         let languages = languages.clone();
-        move |tag_id: String, lang_id: String| {
-            tags.get(tag_id.into())
-                .unwrap()
-                .title(languages.get(lang_id.into()).unwrap())
-                .to_string()
+        move |tag_id: String, lang_id: String| -> Result<_, String> {
+            Ok(get_tag(&tags, &tag_id)?
+                .title(get_lang(&languages, lang_id)?)
+                .to_string())
         }
     });
     engine.add_filter("tag_description", {
         let tags = tags.clone();
         let languages = languages.clone();
-        move |tag_id: String, lang_id: String| {
-            tags.get(tag_id.into())
-                .unwrap()
-                .description(languages.get(lang_id.into()).unwrap())
-                .to_string()
+        move |tag_id: String, lang_id: String| -> Result<_, String> {
+            Ok(get_tag(&tags, &tag_id)?
+                .description(get_lang(&languages, lang_id)?)
+                .to_string())
         }
     });
     engine.add_filter("i18n", {
         let languages = languages.clone();
         let i18n = i18n.clone();
         move |i18n_id: String, lang_id: String| {
-            let i18n_id: IString = i18n_id.into();
-            i18n.display(i18n_id.clone(), languages.get(lang_id.into()).unwrap())
-                .unwrap_or_else(|| panic!("missing i18n {i18n_id}"))
-                .to_string()
+            i18n.format(i18n_id.into(), get_lang(&languages, lang_id)?, &[])
+        }
+    });
+    // e.g. `{{ "greeting" | i18n_with: lang, "name", user }}`
+    engine.add_filter("i18n_with", {
+        let languages = languages.clone();
+        let i18n = i18n.clone();
+        move |i18n_id: String, lang_id: String, name: String, value: upon::Value| {
+            let value = match value {
+                upon::Value::Integer(number) => Arg::Number(number),
+                upon::Value::String(text) => Arg::Text(text.into()),
+                upon::Value::Float(number) => Arg::Text(number.to_string().into()),
+                upon::Value::Bool(boolean) => Arg::Text(boolean.to_string().into()),
+                _ => return Err(format!("value of `{name}` should be a number or a string")),
+            };
+            i18n.format(
+                i18n_id.into(),
+                get_lang(&languages, lang_id)?,
+                &[(&name, value)],
+            )
+        }
+    });
+    // e.g. `{{ pages | len | i18n_count: "pages_count", lang }}`
+    engine.add_filter("i18n_count", {
+        let languages = languages.clone();
+        let i18n = i18n.clone();
+        move |count: i64, i18n_id: String, lang_id: String| {
+            i18n.format(
+                i18n_id.into(),
+                get_lang(&languages, lang_id)?,
+                &[("count", Arg::Number(count))],
+            )
         }
     });
+    engine.add_filter("len", |list: &[upon::Value]| list.len() as i64);
     engine.add_filter("page_title", {
         let metas = metas.clone();
         let languages = languages.clone();
-        move |path_id: String, lang_id: String| {
-            metas
-                .get(path_id.to_ipath())
-                .unwrap()
-                .title(languages.get(lang_id.into()).unwrap())
-                .to_string()
+        move |path_id: String, lang_id: String| -> Result<_, String> {
+            Ok(get_meta(&metas, &path_id)?
+                .title(get_lang(&languages, lang_id)?)
+                .to_string())
         }
     });
     engine.add_filter("page_description", {
         let metas = metas.clone();
         let languages = languages.clone();
-        move |path_id: String, lang_id: String| {
-            metas
-                .get(path_id.to_ipath())
-                .unwrap()
-                .description(languages.get(lang_id.into()).unwrap())
-                .to_string()
+        move |path_id: String, lang_id: String| -> Result<_, String> {
+            Ok(get_meta(&metas, &path_id)?
+                .description(get_lang(&languages, lang_id)?)
+                .to_string())
         }
     });
     engine.add_filter("subpaths", {
         let languages = languages.clone();
         let i18n = i18n.clone();
         move |path_id: String, lang_id: String| {
-            let lang = languages.get(lang_id.into()).unwrap();
+            let lang = get_lang(&languages, lang_id)?;
 
             let mut parts = path_id.split('/').collect_vec();
             if parts.first() != Some(&"") {
@@ -235,33 +418,55 @@ pub fn process_templates(
             (0..parts.len())
                 .map(|i| {
                     let name = match &parts[1..=i] {
-                        ["tags", tag_id] => tags[(*tag_id).into()].title(lang.clone()),
-                        ["tags"] => i18n
-                            .display("all_tags".into(), lang.clone())
-                            .unwrap()
-                            .clone(),
-                        page_id => metas[page_id.iter().collect()].title(lang.clone()),
+                        ["tags"] => i18n.format("all_tags".into(), lang.clone(), &[])?.into(),
+                        // nested tags are at `tags/parent/child`
+                        ["tags", tag_id @ ..] => {
+                            get_tag(&tags, &tag_id.join("/"))?.title(lang.clone())
+                        }
+                        page_id => get_meta(&metas, &page_id.join("/"))?.title(lang.clone()),
                     };
-                    upon::value! {
+                    Ok(upon::value! {
                         path: parts[1..=i].join("/"),
                         name: name,
-                    }
+                    })
                 })
-                .collect_vec()
+                .collect::<Result<Vec<_>, String>>()
         }
     });
 
-    for path in iter_deep(template_dir.clone()) {
-        let path_id = path.strip_prefix(template_dir.clone()).unwrap();
-        let (path_parts, first_part) = path_to_parts_and_first(path_id);
-        let reassembled_path = path_parts
-            .into_iter()
-            .chain(iter::once(first_part))
-            .join("/");
-        engine
-            .add_template(reassembled_path, fs::read_to_string(path).unwrap())
-            .unwrap();
+    let mut paths = IMap::<IString, IPath>::default();
+    if let Err(err) = check_dir(template_dir.clone()) {
+        diagnostics.push(err);
+    } else {
+        paths = iter_deep(template_dir.clone())
+            .filter_map(|path| {
+                let path_id = path.strip_prefix(template_dir.clone()).unwrap();
+                let (path_parts, first_part) = path_to_parts_and_first(path_id);
+                let reassembled_path: IString = path_parts
+                    .into_iter()
+                    .chain(iter::once(first_part))
+                    .join("/")
+                    .into();
+                let source = fs::read_to_string(&path)
+                    .map_err(|err| {
+                        diagnostics.push(
+                            Error::new(ErrorKind::Io, err.to_string()).with_path(path.clone()),
+                        )
+                    })
+                    .ok()?;
+                engine
+                    .add_template(reassembled_path.to_string(), source)
+                    .map_err(|err| diagnostics.push(template_error(err, path.clone())))
+                    .ok()?;
+                Some((reassembled_path, path))
+            })
+            .collect();
     }
 
-    TemplateStore { engine }
+    TemplateStore {
+        engine,
+        paths,
+        feeds: is_absolute_url(&base_url),
+        base_url,
+    }
 }
@@ -1,4 +1,4 @@
-use std::rc::Rc;
-
-#[path = "path.rs"]
-pub mod path;
+use std::rc::Rc;
+
+#[path = "path.rs"]
+pub mod path;